@@ -1,21 +1,82 @@
+use std::{io, ops::Range, path::Path, rc::Rc, time::Instant};
+
+use fastrand::Rng;
+
 use crate::{
     image_formats::ppm::PPM,
+    integrators::{
+        integrator::{Integrator, PathRecord, Sensor, SensorConnection, Termination},
+        path::PathTracer,
+    },
+    render::{
+        adaptive::{AdaptiveSettings, PixelEstimate},
+        aov::{self, AovBuffer, AovImage, AovPixel, AovSample, AovSettings, MaterialIds},
+        denoise::{self, DenoiseSettings},
+        filter::Filter,
+        output::RenderOutput,
+        progress::{CancellationToken, NoProgress, ProgressObserver, ProgressTracker, StderrProgress},
+        progressive::{ProgressiveSettings, RenderState, StateSettings, ViewState},
+        region::{RegionOutput, RenderRegion},
+        roulette::RussianRoulette,
+        stats::{self, RenderStats, StatsOutput, Timings},
+        tonemap::{DisplayTransform, Exposure},
+        trace::PixelTrace,
+    },
     utility::{
         color::Color,
-        interval::Interval,
         ray::Ray,
-        utils::degrees_to_radians,
+        utils::{degrees_to_radians, pixel_rng},
         vec3::{Point3, Precision, Vec3},
     },
 };
 
-use super::hittable::{HitRecord, Hittable};
+use super::{
+    hittable::Hittable,
+    lens::{self, Aperture, TiltShift},
+    lens_system::{LensSystem, RealisticLens},
+    projection::Projection,
+    stereo::{self, Eye, StereoLayout, StereoMode, StereoSettings},
+};
+
+/// Diagonal of a full frame sensor, in meters.
+const FULL_FRAME_DIAGONAL: Precision = 0.04327;
 
 pub struct ImageSettings {
     pub aspect_ratio: Precision,
     pub image_width: i32,
     pub samples_per_pixel: i32,
     pub max_depth: i32,
+    pub seed: u64,
+    /// Stop sampling pixels early once they converge. `samples_per_pixel` is then the
+    /// upper bound on the samples per pixel.
+    pub adaptive: Option<AdaptiveSettings>,
+    pub filter: Filter,
+    /// Exposure and tone mapping applied to the final image.
+    pub display: DisplayTransform,
+    /// Render only part of the image.
+    pub region: Option<RenderRegion>,
+    /// Render in passes, optionally writing checkpoints to resume from.
+    pub progressive: Option<ProgressiveSettings>,
+    /// Report render statistics at the end of `Camera::render`.
+    pub stats: Option<StatsOutput>,
+    /// Render passes such as depth or normals along with the image.
+    pub aovs: Option<AovSettings>,
+    /// Denoise the image, using the albedo and normal passes as guides.
+    pub denoise: Option<DenoiseSettings>,
+    /// Terminate dim paths early. `max_depth` can then be raised for scenes that need
+    /// deep paths, such as nested glass, without wasting time on the rest.
+    pub russian_roulette: Option<RussianRoulette>,
+    /// Clamps the light paths bring back from their second surface on, so that rare
+    /// bright paths such as caustics do not turn into fireflies. This darkens the image
+    /// where such light matters. Only the default `PathTracer` applies it, custom
+    /// integrators have their own settings.
+    pub clamp_indirect: Option<Precision>,
+    /// Print the pixel of every sample that comes out NaN or infinite to stderr. Such
+    /// samples are always counted and left out of the image.
+    pub report_invalid_samples: bool,
+    /// Computes the light reaching the camera. Defaults to a `PathTracer` limited by
+    /// `max_depth`, `russian_roulette` and `clamp_indirect`.
+    pub integrator: Option<Rc<dyn Integrator>>,
 }
 
 impl Default for ImageSettings {
@@ -24,12 +85,38 @@ impl Default for ImageSettings {
         let image_width = 400;
         let samples_per_pixel = 100;
         let max_depth = 50;
+        let seed = 0;
+        let adaptive = None;
+        let filter = Filter::default();
+        let display = DisplayTransform::default();
+        let region = None;
+        let progressive = None;
+        let stats = None;
+        let aovs = None;
+        let denoise = None;
+        let russian_roulette = None;
+        let clamp_indirect = None;
+        let report_invalid_samples = false;
+        let integrator = None;
 
         Self {
             aspect_ratio,
             image_width,
             samples_per_pixel,
             max_depth,
+            seed,
+            adaptive,
+            filter,
+            display,
+            region,
+            progressive,
+            stats,
+            aovs,
+            denoise,
+            russian_roulette,
+            clamp_indirect,
+            report_invalid_samples,
+            integrator,
         }
     }
 }
@@ -39,6 +126,9 @@ pub struct ViewSettings {
     pub look_from: Point3,
     pub look_at: Point3,
     pub vup: Vec3,
+    pub projection: Projection,
+    /// Render a left and a right eye image packed into one.
+    pub stereo: Option<StereoSettings>,
 }
 
 impl Default for ViewSettings {
@@ -47,12 +137,16 @@ impl Default for ViewSettings {
         let look_from = Point3::new(0., 0., 0.);
         let look_at = Point3::new(0., 0., -1.);
         let vup = Vec3::new(0., 1., 0.);
+        let projection = Projection::default();
+        let stereo = None;
 
         Self {
             vfov,
             look_from,
             look_at,
             vup,
+            projection,
+            stereo,
         }
     }
 }
@@ -60,16 +154,33 @@ impl Default for ViewSettings {
 pub struct DefocusSettings {
     pub defocus_angle: Precision,
     pub focus_dist: Precision,
+    pub aperture: Aperture,
+    /// Strength of the cat's eye vignetting towards the image corners, 0 disables it.
+    /// The aperture is clipped by a circle of the same size, offset by this fraction of
+    /// the aperture radius at the left and right image edges.
+    pub cat_eye: Precision,
+    pub tilt_shift: TiltShift,
+    /// Trace rays through a real lens focused at `focus_dist`, instead of the thin lens.
+    /// The field of view then follows from the film size and the lens.
+    pub realistic_lens: Option<RealisticLens>,
 }
 
 impl Default for DefocusSettings {
     fn default() -> Self {
         let defocus_angle = 0.;
         let focus_dist = 10.;
+        let aperture = Aperture::default();
+        let cat_eye = 0.;
+        let tilt_shift = TiltShift::default();
+        let realistic_lens = None;
 
         Self {
             defocus_angle,
             focus_dist,
+            aperture,
+            cat_eye,
+            tilt_shift,
+            realistic_lens,
         }
     }
 }
@@ -82,19 +193,35 @@ pub struct Camera {
     image_width: i32,
     samples_per_pixel: i32,
     max_depth: i32,
+    seed: u64,
+    adaptive: Option<AdaptiveSettings>,
+    filter: Filter,
+    display: DisplayTransform,
+    region: Option<RenderRegion>,
+    progressive: Option<ProgressiveSettings>,
+    stats: Option<StatsOutput>,
+    aovs: Option<AovSettings>,
+    denoise: Option<DenoiseSettings>,
+    russian_roulette: Option<RussianRoulette>,
+    clamp_indirect: Option<Precision>,
+    report_invalid_samples: bool,
+    integrator: Option<Rc<dyn Integrator>>,
+    /// The integrator actually used, the default one if none was given.
+    active_integrator: Rc<dyn Integrator>,
 
     vfov: Precision,
     look_from: Point3,
     look_at: Point3,
     vup: Vec3,
+    projection: Projection,
+    stereo: Option<StereoSettings>,
 
     // Calculated from inputs.
-    pixel_samples_scale: Precision, // color scale factor for a sum of pixel samples
-    image_height: i32,              // height of rendered image
-    center: Point3,                 // camera center
-    pixel00_loc: Point3,            // location of pixel 0,0
-    pixel_delta_u: Vec3,            // offset to pixel to the right
-    pixel_delta_v: Vec3,            // offset to pixel below
+    image_height: i32,   // height of rendered image
+    center: Point3,      // camera center
+    pixel00_loc: Point3, // location of pixel 0,0
+    pixel_delta_u: Vec3, // offset to pixel to the right
+    pixel_delta_v: Vec3, // offset to pixel below
 
     // Camera frame basis vectors.
     u: Vec3,
@@ -106,22 +233,43 @@ pub struct Camera {
     focus_dist: Precision,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    aperture: Aperture,
+    cat_eye: Precision,
+    tilt_shift: TiltShift,
+    focus_plane_normal: Vec3,
+    realistic_lens: Option<RealisticLens>,
+    lens_system: Option<Rc<LensSystem>>,
+
+    // Omni-directional stereo eye, for panoramic projections.
+    eye_offset: Precision,              // signed distance from the rig center
+    eye_convergence: Option<Precision>, // distance the eyes converge at, if toed in
 }
 
 impl Camera {
+    /// # Panics
+    ///
+    /// If the realistic lens cannot focus at the focus distance, see `try_new`.
     pub fn new(
         image_settings: ImageSettings,
         view_settings: ViewSettings,
         defocus_settings: DefocusSettings,
     ) -> Self {
+        Self::try_new(image_settings, view_settings, defocus_settings).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like `new`, but fails instead of panicking when the realistic lens blocks the rays
+    /// near its axis or cannot focus at the focus distance.
+    pub fn try_new(
+        image_settings: ImageSettings,
+        view_settings: ViewSettings,
+        defocus_settings: DefocusSettings,
+    ) -> io::Result<Self> {
         // Calculate the image height, and ensure that it's at least 1.
 
         let image_height =
             (image_settings.image_width as Precision / image_settings.aspect_ratio) as i32;
         let image_height = if image_height < 1 { 1 } else { image_height };
 
-        let pixel_samples_scale = 1.0 / image_settings.samples_per_pixel as Precision;
-
         // Camera
 
         let center = view_settings.look_from;
@@ -150,8 +298,10 @@ impl Camera {
 
         // Calculate the location of the upper left pixel.
 
-        let viewport_upper_left =
-            center - (defocus_settings.focus_dist * w) - viewport_u / 2. - viewport_v / 2.;
+        let tilt_shift = defocus_settings.tilt_shift;
+        let viewport_upper_left = center - (defocus_settings.focus_dist * w) - viewport_u / 2. - viewport_v / 2.
+            + tilt_shift.shift_x * viewport_u
+            - tilt_shift.shift_y * viewport_v;
         let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
         // Calculate the camera defocus disk basis vectors.
@@ -160,17 +310,56 @@ impl Camera {
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Self {
+        // Tilt the plane of focus, first around the horizontal axis and then around the
+        // vertical one.
+
+        let focus_plane_normal = lens::rotate(lens::rotate(w, u, tilt_shift.tilt), v, tilt_shift.swing);
+
+        let lens_system = match &defocus_settings.realistic_lens {
+            Some(realistic_lens) => {
+                let aspect_ratio = image_settings.image_width as Precision / image_height as Precision;
+                Some(Rc::new(LensSystem::new(realistic_lens, defocus_settings.focus_dist, aspect_ratio)?))
+            }
+            None => None,
+        };
+
+        if image_settings.integrator.is_some() && image_settings.clamp_indirect.is_some() {
+            eprintln!("clamp_indirect only applies to the default path tracer, and is ignored for the given integrator");
+        }
+        let active_integrator = image_settings.integrator.clone().unwrap_or_else(|| {
+            Rc::new(PathTracer {
+                max_depth: image_settings.max_depth,
+                russian_roulette: image_settings.russian_roulette,
+                clamp_indirect: image_settings.clamp_indirect,
+            })
+        });
+
+        Ok(Self {
             aspect_ratio: image_settings.aspect_ratio,
             image_width: image_settings.image_width,
             samples_per_pixel: image_settings.samples_per_pixel,
             max_depth: image_settings.max_depth,
+            seed: image_settings.seed,
+            adaptive: image_settings.adaptive,
+            filter: image_settings.filter,
+            display: image_settings.display,
+            region: image_settings.region,
+            progressive: image_settings.progressive,
+            stats: image_settings.stats,
+            aovs: image_settings.aovs,
+            denoise: image_settings.denoise,
+            russian_roulette: image_settings.russian_roulette,
+            clamp_indirect: image_settings.clamp_indirect,
+            report_invalid_samples: image_settings.report_invalid_samples,
+            integrator: image_settings.integrator,
+            active_integrator,
             vfov: view_settings.vfov,
             look_from: view_settings.look_from,
             look_at: view_settings.look_at,
             vup: view_settings.vup,
+            projection: view_settings.projection,
+            stereo: view_settings.stereo,
 
-            pixel_samples_scale,
             image_height,
             center,
             pixel00_loc,
@@ -185,71 +374,832 @@ impl Camera {
             focus_dist: defocus_settings.focus_dist,
             defocus_disk_u,
             defocus_disk_v,
+            aperture: defocus_settings.aperture,
+            cat_eye: defocus_settings.cat_eye,
+            tilt_shift,
+            focus_plane_normal,
+            realistic_lens: defocus_settings.realistic_lens,
+            lens_system,
+
+            eye_offset: 0.,
+            eye_convergence: None,
+        })
+    }
+
+    /// f-number of the lens, `None` for a pinhole camera. The thin lens is taken as the
+    /// lens of a full frame (36 x 24 mm) camera with the same field of view, in a scene
+    /// measured in meters.
+    pub fn f_number(&self) -> Option<Precision> {
+        if let Some(lens_system) = &self.lens_system {
+            return lens_system.f_number();
+        }
+        if self.defocus_angle <= 0. {
+            return None;
         }
+
+        let aspect_ratio = self.image_width as Precision / self.image_height as Precision;
+        let half_diagonal = degrees_to_radians(self.vfov / 2.).tan() * (1. + aspect_ratio * aspect_ratio).sqrt();
+        let focal_length = FULL_FRAME_DIAGONAL / 2. / half_diagonal;
+        let aperture = 2. * self.focus_dist * degrees_to_radians(self.defocus_angle / 2.).tan();
+        Some(focal_length / aperture)
+    }
+
+    /// The camera, exposed like a physical camera at `iso` and `shutter` seconds through
+    /// its own lens, see `f_number`. Fails for pinhole cameras, which let no light in.
+    pub fn with_physical_exposure(mut self, iso: Precision, shutter: Precision) -> io::Result<Self> {
+        let f_number = self.f_number().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "a pinhole camera has no f-number to expose with")
+        })?;
+        self.display.exposure = Exposure::Physical { iso, shutter, f_number };
+        Ok(self)
+    }
+
+    /// Settings that build this camera.
+    fn settings(&self) -> (ImageSettings, ViewSettings, DefocusSettings) {
+        let image_settings = ImageSettings {
+            aspect_ratio: self.aspect_ratio,
+            image_width: self.image_width,
+            samples_per_pixel: self.samples_per_pixel,
+            max_depth: self.max_depth,
+            seed: self.seed,
+            adaptive: self.adaptive,
+            filter: self.filter,
+            display: self.display,
+            region: self.region,
+            progressive: self.progressive.clone(),
+            stats: self.stats.clone(),
+            aovs: self.aovs.clone(),
+            denoise: self.denoise,
+            russian_roulette: self.russian_roulette,
+            clamp_indirect: self.clamp_indirect,
+            report_invalid_samples: self.report_invalid_samples,
+            integrator: self.integrator.clone(),
+        };
+        let view_settings = ViewSettings {
+            vfov: self.vfov,
+            look_from: self.look_from,
+            look_at: self.look_at,
+            vup: self.vup,
+            projection: self.projection,
+            stereo: self.stereo,
+        };
+        let defocus_settings = DefocusSettings {
+            defocus_angle: self.defocus_angle,
+            focus_dist: self.focus_dist,
+            aperture: self.aperture.clone(),
+            cat_eye: self.cat_eye,
+            tilt_shift: self.tilt_shift,
+            realistic_lens: self.realistic_lens.clone(),
+        };
+
+        (image_settings, view_settings, defocus_settings)
+    }
+
+    /// Mono camera for one eye of the stereo rig.
+    fn eye_camera(&self, stereo: &StereoSettings, eye: Eye) -> Camera {
+        let offset = eye.sign() * stereo.interocular_distance / 2.;
+
+        if matches!(self.projection, Projection::Equirectangular | Projection::Cylindrical { .. }) {
+            let mut camera = self.clone();
+            camera.stereo = None;
+            camera.eye_offset = offset;
+            camera.eye_convergence =
+                (stereo.mode == StereoMode::ToeIn).then_some(stereo.convergence_distance);
+            return camera;
+        }
+
+        let (image_settings, mut view_settings, defocus_settings) = self.settings();
+        view_settings.stereo = None;
+        view_settings.look_from = self.look_from + offset * self.u;
+        view_settings.look_at = match stereo.mode {
+            StereoMode::Parallel => self.look_at + offset * self.u,
+            StereoMode::ToeIn => self.look_from - stereo.convergence_distance * self.w,
+        };
+
+        let mut camera = Camera::new(image_settings, view_settings, defocus_settings);
+        if stereo.mode == StereoMode::Parallel {
+            // Shift the viewport towards the rig center so the eyes' views meet at the
+            // convergence distance.
+            camera.pixel00_loc -= (offset * self.focus_dist / stereo.convergence_distance) * self.u;
+        }
+
+        camera
     }
 
+    /// Renders the world to stdout, reporting progress on stderr and the statistics as
+    /// asked for by `ImageSettings::stats`.
     pub fn render(&self, world: &dyn Hittable) {
-        let ppm = PPM::generate(
-            self.image_width as usize,
-            self.image_height as usize,
-            255,
-            |row, col| {
-                let mut pixel_color = Color::new(0., 0., 0.);
-
-                for _sample in 0..self.samples_per_pixel {
-                    let r = self.get_ray(col as i32, row as i32);
-                    pixel_color += Camera::ray_color(&r, self.max_depth, world);
+        let output = self.render_with(world, &mut StderrProgress::default(), &CancellationToken::default());
+
+        let start = Instant::now();
+        output.image.output();
+
+        if let Some(AovSettings { output: Some(aov_output), .. }) = &self.aovs {
+            if let Err(err) = aov::write_aovs(&output.aovs, aov_output, &self.display) {
+                eprintln!("Could not write render passes: {err}");
+            }
+        }
+
+        if let Some(stats_output) = &self.stats {
+            let mut stats = output.stats;
+            stats.timings.output = start.elapsed();
+            if let Err(err) = stats.report(stats_output) {
+                eprintln!("Could not write render statistics: {err}");
+            }
+        }
+    }
+
+    /// Renders the world into an image without writing it anywhere. Two renders with
+    /// the same seed produce the same image.
+    pub fn render_to_ppm(&self, world: &dyn Hittable) -> PPM {
+        self.render_output(world).image
+    }
+
+    /// Renders the world, also returning the number of samples taken for each pixel.
+    pub fn render_output(&self, world: &dyn Hittable) -> RenderOutput {
+        self.render_with(world, &mut NoProgress, &CancellationToken::default())
+    }
+
+    /// Renders the world, reporting progress to `observer`. Cancelling `cancel` stops the
+    /// render as soon as possible and returns what was rendered so far.
+    pub fn render_with(
+        &self,
+        world: &dyn Hittable,
+        observer: &mut dyn ProgressObserver,
+        cancel: &CancellationToken,
+    ) -> RenderOutput {
+        self.render_from(world, self.new_state(), observer, cancel)
+    }
+
+    /// Picks a render up from a checkpoint written by an earlier render with the same
+    /// settings. Raising `samples_per_pixel` extends the earlier render with more samples.
+    pub fn resume(&self, world: &dyn Hittable, checkpoint: impl AsRef<Path>) -> io::Result<RenderOutput> {
+        self.resume_with(world, checkpoint, &mut NoProgress, &CancellationToken::default())
+    }
+
+    /// Like `Camera::resume`, reporting progress and allowing cancellation like
+    /// `Camera::render_with`.
+    pub fn resume_with(
+        &self,
+        world: &dyn Hittable,
+        checkpoint: impl AsRef<Path>,
+        observer: &mut dyn ProgressObserver,
+        cancel: &CancellationToken,
+    ) -> io::Result<RenderOutput> {
+        let mut state = RenderState::load(checkpoint, self.filter)?;
+        let expected = self.new_state();
+
+        let size = (self.image_width as usize, self.image_height as usize);
+        let passes = state.views.iter().zip(&expected.views).all(|(a, b)| a.aovs.is_some() == b.aovs.is_some());
+        let (saved, wanted) = (&state.settings, &expected.settings);
+        let mismatch = [
+            ("seed", state.seed != expected.seed),
+            ("number of views", state.views.len() != expected.views.len()),
+            ("image size", state.views.iter().any(|view| (view.film.cols(), view.film.rows()) != size)),
+            ("render passes", !passes),
+            ("filter", saved.filter != wanted.filter),
+            ("render region", saved.region != wanted.region),
+            ("integrator", saved.integrator != wanted.integrator),
+            ("view", saved.view != wanted.view),
+            ("lens", saved.lens != wanted.lens),
+            ("adaptive sampling", saved.adaptive != wanted.adaptive),
+            ("samples per pixel, which can only be raised", saved.samples_per_pixel > wanted.samples_per_pixel),
+        ]
+        .into_iter()
+        .find_map(|(setting, differs)| differs.then_some(setting));
+        if let Some(setting) = mismatch {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("checkpoint does not match the camera's {setting}"),
+            ));
+        }
+
+        state.settings = expected.settings;
+
+        Ok(self.render_from(world, state, observer, cancel))
+    }
+
+    /// Mono cameras for every view the camera renders.
+    fn views(&self) -> Vec<Camera> {
+        match self.stereo {
+            Some(stereo) => vec![self.eye_camera(&stereo, Eye::Left), self.eye_camera(&stereo, Eye::Right)],
+            None => vec![self.clone()],
+        }
+    }
+
+    fn new_state(&self) -> RenderState {
+        let views = if self.stereo.is_some() { 2 } else { 1 };
+        let (cols, rows) = (self.image_width as usize, self.image_height as usize);
+        let mut view = ViewState::new(cols, rows, self.filter);
+        if self.aovs.is_some() || self.denoise.is_some() {
+            view.aovs = Some(AovBuffer::new(cols, rows));
+        }
+
+        RenderState {
+            seed: self.seed,
+            settings: StateSettings {
+                samples_per_pixel: self.samples_per_pixel,
+                filter: format!("{:?}", self.filter),
+                region: format!("{:?}", self.region.map(|region| region.region)),
+                integrator: format!("{:?}", self.active_integrator),
+                view: format!(
+                    "{:?}",
+                    (self.look_from, self.look_at, self.vup, self.vfov, self.projection, self.stereo)
+                ),
+                lens: format!(
+                    "{:?}",
+                    (
+                        self.defocus_angle,
+                        self.focus_dist,
+                        &self.aperture,
+                        self.cat_eye,
+                        self.tilt_shift,
+                        &self.realistic_lens
+                    )
+                ),
+                adaptive: format!("{:?}", self.adaptive),
+            },
+            views: vec![view; views],
+        }
+    }
+
+    /// Keeps adding passes of samples to `state` until every pixel is done or the render
+    /// is cancelled, writing checkpoints along the way.
+    fn render_from(
+        &self,
+        world: &dyn Hittable,
+        mut state: RenderState,
+        observer: &mut dyn ProgressObserver,
+        cancel: &CancellationToken,
+    ) -> RenderOutput {
+        let start = Instant::now();
+        let counting = stats::set_enabled(self.stats.is_some());
+        let counters = stats::snapshot();
+        let mut timings = Timings::default();
+
+        let cameras = self.views();
+        let samples_per_pass = self
+            .progressive
+            .as_ref()
+            .map_or(self.samples_per_pixel, |progressive| progressive.samples_per_pass.max(1));
+        let mut last_checkpoint = Instant::now();
+
+        let (sampled_rows, sampled_cols) = self.sampled_ranges();
+        let cols = self.image_width as usize;
+        let pixels = (sampled_rows.len() * sampled_cols.len() * state.views.len()) as u64;
+        let mut initial_samples = 0;
+        for view in &state.views {
+            for row in sampled_rows.clone() {
+                for col in sampled_cols.clone() {
+                    initial_samples += view.estimates[row * cols + col].count() as u64;
                 }
+            }
+        }
+        let mut tracker = ProgressTracker::new(pixels * self.samples_per_pixel.max(0) as u64, initial_samples);
+        timings.setup = start.elapsed();
+
+        // Resumed renders carry on counting the passes where they left off.
+        let mut pass = initial_samples / (pixels * samples_per_pass.max(0) as u64).max(1);
+        loop {
+            tracker.start_pass();
 
-                self.pixel_samples_scale * pixel_color
+            let pass_start = Instant::now();
+            self.active_integrator.start_pass(world, pass);
+            pass += 1;
+            let mut finished = true;
+            for (camera, view) in cameras.iter().zip(&mut state.views) {
+                finished &= camera.render_pass(world, view, samples_per_pass, &mut tracker, observer, cancel);
+            }
+            timings.sampling += pass_start.elapsed();
+
+            let cancelled = cancel.is_cancelled();
+
+            if let Some(ProgressiveSettings {
+                checkpoint: Some(path),
+                checkpoint_interval,
+                ..
+            }) = &self.progressive
+            {
+                if finished || cancelled || last_checkpoint.elapsed() >= *checkpoint_interval {
+                    let checkpoint_start = Instant::now();
+                    if let Err(err) = state.save(path) {
+                        eprintln!("Could not write checkpoint {}: {err}", path.display());
+                    }
+                    timings.checkpointing += checkpoint_start.elapsed();
+                    last_checkpoint = Instant::now();
+                }
+            }
+
+            if finished || cancelled {
+                break;
+            }
+        }
+
+        let cancelled = cancel.is_cancelled();
+        observer.on_finished(&tracker.progress(), cancelled);
+
+        let resolve_start = Instant::now();
+        let output = self.resolve(&cameras, &state);
+        timings.resolve = resolve_start.elapsed();
+        let counters = stats::snapshot() - counters;
+        stats::set_enabled(counting);
+
+        RenderOutput {
+            cancelled,
+            stats: RenderStats {
+                counters,
+                timings,
             },
-        );
+            ..output
+        }
+    }
+
+    /// Turns the films of the views into the final image.
+    fn resolve(&self, cameras: &[Camera], state: &RenderState) -> RenderOutput {
+        // Both eyes share the material numbering.
+        let mut materials = MaterialIds::default();
+        let mut outputs = cameras
+            .iter()
+            .zip(&state.views)
+            .map(|(camera, view)| camera.view_output(view, &mut materials));
+        let Some(stereo) = self.stereo else {
+            return outputs.next().unwrap();
+        };
+
+        let (left, right) = (outputs.next().unwrap(), outputs.next().unwrap());
+        let (cols, rows) = (left.image.cols(), left.image.rows());
+        let values = stereo::combine(left.image.values(), right.image.values(), cols, rows, stereo.layout);
+        let sample_counts = stereo::combine(&left.sample_counts, &right.sample_counts, cols, rows, stereo.layout);
+        let (combined_cols, combined_rows) = match stereo.layout {
+            StereoLayout::SideBySide => (2 * cols, rows),
+            StereoLayout::OverUnder => (cols, 2 * rows),
+        };
+        let aovs = left
+            .aovs
+            .iter()
+            .zip(&right.aovs)
+            .map(|(left, right)| {
+                let channels = left.aov.channels().len();
+                AovImage {
+                    aov: left.aov,
+                    cols: combined_cols,
+                    rows: combined_rows,
+                    values: stereo::combine(&left.values, &right.values, channels * cols, rows, stereo.layout),
+                }
+            })
+            .collect();
+
+        RenderOutput {
+            image: PPM::new(combined_cols, combined_rows, 255, values),
+            sample_counts,
+            cancelled: false,
+            stats: RenderStats::default(),
+            aovs,
+        }
+    }
+
+    /// Rows and columns of the render region.
+    fn region_ranges(&self) -> (Range<usize>, Range<usize>) {
+        let cols = self.image_width as usize;
+        let rows = self.image_height as usize;
+
+        match self.region {
+            Some(region) => region.region.pixel_ranges(cols, rows),
+            None => (0..rows, 0..cols),
+        }
+    }
+
+    /// Rows and columns of the pixels sampled to render the region.
+    fn sampled_ranges(&self) -> (Range<usize>, Range<usize>) {
+        let cols = self.image_width as usize;
+        let rows = self.image_height as usize;
+        let (region_rows, region_cols) = self.region_ranges();
+
+        // Pixels just outside the region splat samples into it, sample them too.
+        let margin = (self.filter.radius() - 0.5).ceil().max(0.) as usize;
+        (
+            region_rows.start.saturating_sub(margin)..(region_rows.end + margin).min(rows),
+            region_cols.start.saturating_sub(margin)..(region_cols.end + margin).min(cols),
+        )
+    }
+
+    /// Adds up to `samples` samples to every unfinished pixel of the view. Returns whether
+    /// all the pixels are finished, which they are not if the render was cancelled.
+    fn render_pass(
+        &self,
+        world: &dyn Hittable,
+        view: &mut ViewState,
+        samples: i32,
+        tracker: &mut ProgressTracker,
+        observer: &mut dyn ProgressObserver,
+        cancel: &CancellationToken,
+    ) -> bool {
+        let cols = self.image_width as usize;
+        let (sampled_rows, sampled_cols) = self.sampled_ranges();
+
+        let mut finished = true;
+        for row in sampled_rows {
+            if cancel.is_cancelled() {
+                return false;
+            }
+
+            for col in sampled_cols.clone() {
+                let before = view.estimates[row * cols + col].count();
+                let target = before.saturating_add(samples).min(self.samples_per_pixel);
+                let rays = self.sample_pixel(world, row, col, target, view);
+
+                let estimate = &view.estimates[row * cols + col];
+                tracker.add((estimate.count() - before) as u64, rays);
+                finished &= self.pixel_finished(estimate);
+            }
+
+            observer.on_progress(&tracker.progress());
+        }
+
+        finished
+    }
+
+    /// Output image, sample counts and passes of the view.
+    fn view_output(&self, view: &ViewState, materials: &mut MaterialIds) -> RenderOutput {
+        let cols = self.image_width as usize;
+        let rows = self.image_height as usize;
+        let (region_rows, region_cols) = self.region_ranges();
 
-        ppm.output();
+        let in_region = |row: usize, col: usize| region_rows.contains(&row) && region_cols.contains(&col);
+        let (out_rows, out_cols) = match self.region.map(|region| region.output) {
+            Some(RegionOutput::Cropped) => (region_rows.clone(), region_cols.clone()),
+            _ => (0..rows, 0..cols),
+        };
+
+        let denoised = self.denoise_region(view);
+        let linear = |row: usize, col: usize| match &denoised {
+            Some(denoised) => denoised[(row - region_rows.start) * region_cols.len() + col - region_cols.start],
+            None => view.film.pixel(row, col),
+        };
+
+        let mut values = Vec::with_capacity(out_rows.len() * out_cols.len());
+        let mut sample_counts = Vec::with_capacity(out_rows.len() * out_cols.len());
+        for row in out_rows.clone() {
+            for col in out_cols.clone() {
+                if in_region(row, col) {
+                    values.push(self.display.apply(linear(row, col)));
+                    sample_counts.push(view.estimates[row * cols + col].count());
+                } else {
+                    values.push(Color::new(0., 0., 0.));
+                    sample_counts.push(0);
+                }
+            }
+        }
+
+        let mut aovs = Vec::new();
+        if let (Some(settings), Some(buffer)) = (&self.aovs, &view.aovs) {
+            for &aov in &settings.aovs {
+                let mut values = Vec::with_capacity(out_rows.len() * out_cols.len() * aov.channels().len());
+                for row in out_rows.clone() {
+                    for col in out_cols.clone() {
+                        let pixel = in_region(row, col).then(|| buffer.pixel(row, col));
+                        values.extend(AovPixel::values(pixel, aov, materials));
+                    }
+                }
+
+                aovs.push(AovImage {
+                    aov,
+                    cols: out_cols.len(),
+                    rows: out_rows.len(),
+                    values,
+                });
+            }
+        }
+
+        RenderOutput {
+            image: PPM::new(out_cols.len(), out_rows.len(), 255, values),
+            sample_counts,
+            cancelled: false,
+            stats: RenderStats::default(),
+            aovs,
+        }
     }
 
-    fn ray_color(r: &Ray, depth: i32, world: &dyn Hittable) -> Color {
-        if depth <= 0 {
-            return Color::new(0., 0., 0.);
+    /// Denoised linear colors of the render region, in row major order, if denoising.
+    fn denoise_region(&self, view: &ViewState) -> Option<Vec<Color>> {
+        let (settings, buffer) = (self.denoise.as_ref()?, view.aovs.as_ref()?);
+        let (region_rows, region_cols) = self.region_ranges();
+
+        let pixels = region_rows.len() * region_cols.len();
+        let mut color = Vec::with_capacity(pixels);
+        let mut albedo = Vec::with_capacity(pixels);
+        let mut normal = Vec::with_capacity(pixels);
+        for row in region_rows.clone() {
+            for col in region_cols.clone() {
+                color.push(view.film.pixel(row, col));
+                albedo.push(buffer.pixel(row, col).albedo());
+                normal.push(buffer.pixel(row, col).normal());
+            }
         }
 
-        let mut rec = HitRecord::default();
-        if world.hit(r, Interval::new(0.001, Precision::INFINITY), &mut rec) {
-            if let Some(scattered_ray) = rec.material.scatter(r, &rec) {
-                return scattered_ray.attenuation
-                    * Camera::ray_color(&scattered_ray.ray, depth - 1, world);
+        Some(denoise::denoise(&color, &albedo, &normal, region_cols.len(), region_rows.len(), settings))
+    }
+
+    /// Whether a pixel has all its samples, or has converged under adaptive sampling.
+    fn pixel_finished(&self, estimate: &PixelEstimate) -> bool {
+        estimate.count() >= self.samples_per_pixel
+            || self.adaptive.is_some_and(|adaptive| estimate.converged(&adaptive))
+    }
+
+    /// Samples the pixel at `row`, `col` until it has `target` samples or is finished,
+    /// splatting the samples into the film. Returns the number of rays traced.
+    fn sample_pixel(&self, world: &dyn Hittable, row: usize, col: usize, target: i32, view: &mut ViewState) -> u64 {
+        let estimate = &mut view.estimates[row * self.image_width as usize + col];
+        let mut rays = 0;
+        let mut camera_rays = 0;
+        let mut max_depth_terminations = 0;
+        let mut russian_roulette_terminations = 0;
+        let mut invalid_samples = 0;
+
+        while estimate.count() < target && !self.pixel_finished(estimate) {
+            let mut rng = pixel_rng(self.seed, row as u64, col as u64, estimate.count() as u64);
+            let mut path = PathRecord {
+                aov: view.aovs.is_some().then(AovSample::default),
+                ..Default::default()
+            };
+
+            let offset = Camera::sample_square(&mut rng);
+            let color = match self.get_ray(col as i32, row as i32, offset, &mut rng) {
+                Some(r) => {
+                    camera_rays += 1;
+                    let sensor = self.sensor();
+                    let color = self.active_integrator.radiance(&r.ray, world, sensor, &mut rng, &mut path);
+                    rays += path.rays;
+                    for splat in &path.splats {
+                        let light = r.weight * splat.color;
+                        if light.is_finite() {
+                            view.film.add_light(splat.x, splat.y, light);
+                        } else {
+                            invalid_samples += 1;
+                            self.report_invalid_sample(row, col, estimate.count(), "light splat", light);
+                        }
+                    }
+                    if let Some(aov) = &mut path.aov {
+                        aov.scale_light(r.weight);
+                    }
+                    match path.termination {
+                        Termination::MaxDepth => max_depth_terminations += 1,
+                        Termination::RussianRoulette => russian_roulette_terminations += 1,
+                        Termination::Escaped | Termination::Absorbed => (),
+                    }
+                    r.weight * color
+                }
+                None => Color::new(0., 0., 0.),
+            };
+
+            // One NaN would spread over the whole pixel, and through the filter to its
+            // neighbours, so invalid samples are left out of the film and the estimate.
+            view.film.add_light_paths(1);
+            if color.is_finite() {
+                view.film.add_sample(
+                    col as Precision + 0.5 + offset.x(),
+                    row as Precision + 0.5 + offset.y(),
+                    color,
+                );
+                estimate.add(color);
+            } else {
+                invalid_samples += 1;
+                self.report_invalid_sample(row, col, estimate.count(), "sample", color);
+                estimate.skip();
+                if let Some(aov) = &mut path.aov {
+                    *aov = AovSample {
+                        surface: aov.surface,
+                        ..Default::default()
+                    };
+                }
+            }
+            if let (Some(buffer), Some(aov)) = (&mut view.aovs, &path.aov) {
+                buffer.add_sample(row, col, aov);
             }
-            return Color::new(0., 0., 0.);
         }
 
-        let unit_direction = r.direction().unit_vec();
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        lerp(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0), a)
+        stats::record(|counters| {
+            counters.camera_rays += camera_rays;
+            counters.secondary_rays += rays.saturating_sub(camera_rays);
+            counters.max_depth_terminations += max_depth_terminations;
+            counters.russian_roulette_terminations += russian_roulette_terminations;
+            counters.invalid_samples += invalid_samples;
+        });
+
+        rays
+    }
+
+    fn report_invalid_sample(&self, row: usize, col: usize, sample: i32, what: &str, value: Color) {
+        if self.report_invalid_samples {
+            eprintln!("Invalid {what} {value} at row {row}, col {col}, sample {sample}");
+        }
+    }
+
+    /// The camera as a target for light paths, if its projection supports that. Only
+    /// pinhole perspective cameras do.
+    fn sensor(&self) -> Option<&dyn Sensor> {
+        let pinhole = self.projection == Projection::Perspective
+            && self.lens_system.is_none()
+            && self.defocus_angle <= 0.
+            && !self.tilt_shift.is_tilted()
+            && self.eye_offset == 0.;
+
+        pinhole.then_some(self as &dyn Sensor)
+    }
+
+    /// Traces sample `sample` of the pixel at `row`, `col` again, exactly as the render
+    /// takes it, recording every surface the camera path hits along the way. Stereo
+    /// cameras are traced as if they were not.
+    ///
+    /// The integrator is left as it is, so integrators that prepare every pass, like the
+    /// photon mapper, trace with what they prepared for the last pass rendered.
+    pub fn trace_pixel(&self, world: &dyn Hittable, row: usize, col: usize, sample: u64) -> PixelTrace {
+        let mut rng = pixel_rng(self.seed, row as u64, col as u64, sample);
+        let mut path = PathRecord {
+            trace: Some(Vec::new()),
+            ..Default::default()
+        };
+
+        let offset = Camera::sample_square(&mut rng);
+        let camera_ray = self.get_ray(col as i32, row as i32, offset, &mut rng);
+        let (ray, weight, color) = match camera_ray {
+            Some(r) => {
+                let color = self.active_integrator.radiance(&r.ray, world, self.sensor(), &mut rng, &mut path);
+                (Some(r.ray), r.weight, r.weight * color)
+            }
+            None => (None, Color::new(0., 0., 0.), Color::new(0., 0., 0.)),
+        };
+
+        PixelTrace {
+            row,
+            col,
+            sample,
+            ray,
+            weight,
+            color,
+            rays: path.rays,
+            termination: path.termination,
+            bounces: path.trace.unwrap_or_default(),
+        }
     }
 
-    /// Construct a camera ray originating from the defocus disk and directed at randomly
-    /// sampled point around the pixel location i, j.
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
+    /// Construct a camera ray through the point `offset` away from the pixel location
+    /// i, j, or `None` if the projection sees nothing there.
+    fn get_ray(&self, i: i32, j: i32, offset: Vec3, rng: &mut Rng) -> Option<CameraRay> {
+        let s = (i as Precision + 0.5 + offset.x()) / self.image_width as Precision;
+        let t = (j as Precision + 0.5 + offset.y()) / self.image_height as Precision;
 
-        let offset = Camera::sample_square();
+        if let Some(lens_system) = &self.lens_system {
+            let (local, weight) = lens_system.generate_ray(s, t, rng)?;
+            let ray = Ray::new(self.center + self.to_world(*local.origin()), self.to_world(*local.direction()));
+            return Some(CameraRay { ray, weight });
+        }
+
+        if self.projection == Projection::Perspective {
+            return self.perspective_ray(i, j, offset, rng).map(CameraRay::unweighted);
+        }
+
+        let aspect_ratio = self.image_width as Precision / self.image_height as Precision;
+
+        let local = self.projection.camera_space_ray(s, t, aspect_ratio)?;
+        let mut origin = *local.origin();
+        let mut direction = *local.direction();
+
+        if self.eye_offset != 0. {
+            // Offset the eye sideways from the horizontal viewing direction.
+            let horizontal = Vec3::new(direction.x(), 0., direction.z()).unit_vec();
+            let side = Vec3::new(-horizontal.z(), 0., horizontal.x());
+            origin += self.eye_offset * side;
+
+            if let Some(convergence) = self.eye_convergence {
+                direction = convergence * direction.unit_vec() - self.eye_offset * side;
+            }
+        }
+
+        let ray = Ray::new(self.center + self.to_world(origin), self.to_world(direction));
+        Some(CameraRay::unweighted(ray))
+    }
+
+    /// Construct a camera ray originating from the defocus disk and directed at the point
+    /// `offset` away from the pixel location i, j. Returns `None` if the lens barrel
+    /// blocks the sampled ray.
+    fn perspective_ray(&self, i: i32, j: i32, offset: Vec3, rng: &mut Rng) -> Option<Ray> {
         let pixel_center = self.pixel00_loc
             + ((i as Precision + offset.x()) * self.pixel_delta_u)
             + ((j as Precision + offset.y()) * self.pixel_delta_v);
 
-        let ray_origin = if self.defocus_angle <= 0. { self.center } else { self.defocus_disk_sample() };
-        let ray_direction = pixel_center - ray_origin;
+        let focus_point = if self.tilt_shift.is_tilted() {
+            // Where the ray through the lens center meets the tilted plane of focus.
+            let direction = pixel_center - self.center;
+            let plane_point = self.center - self.focus_dist * self.w;
+            let t = (plane_point - self.center).dot(&self.focus_plane_normal)
+                / direction.dot(&self.focus_plane_normal);
+
+            if t > 0. && t.is_finite() {
+                self.center + t * direction
+            } else {
+                // The plane of focus is behind the lens, treat the ray as focused at infinity.
+                let ray_origin = self.defocus_disk_sample(i, j, offset, rng)?;
+                return Some(Ray::new(ray_origin, direction));
+            }
+        } else {
+            pixel_center
+        };
+
+        let ray_origin = self.defocus_disk_sample(i, j, offset, rng)?;
+        let ray_direction = focus_point - ray_origin;
+
+        Some(Ray::new(ray_origin, ray_direction))
+    }
+
+    /// Transforms a vector from camera space to world space.
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x() * self.u + v.y() * self.v + v.z() * self.w
+    }
+
+    fn sample_square(rng: &mut Rng) -> Vec3 {
+        Vec3::new(rng.f32() - 0.5, rng.f32() - 0.5, 0.)
+    }
+
+    /// Returns a random point on the camera aperture for a ray through the point `offset`
+    /// away from the pixel location i, j, or `None` if the lens barrel vignettes it.
+    fn defocus_disk_sample(&self, i: i32, j: i32, offset: Vec3, rng: &mut Rng) -> Option<Point3> {
+        if self.defocus_angle <= 0. {
+            return Some(self.center);
+        }
+
+        let p = self.aperture.sample(rng);
 
-        Ray::new(ray_origin, ray_direction)
+        if self.cat_eye > 0. {
+            // Image position with the left and right edges at -1 and 1.
+            let width = self.image_width as Precision;
+            let x = 2. * (i as Precision + 0.5 + offset.x()) / width - 1.;
+            let y = (self.image_height as Precision - 2. * (j as Precision + 0.5 + offset.y())) / width;
+            let barrel = self.cat_eye * Vec3::new(x, y, 0.);
+
+            if (p - barrel).len_square() > 1. {
+                return None;
+            }
+        }
+
+        Some(self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v))
     }
+}
 
-    fn sample_square() -> Vec3 {
-        Vec3::new(fastrand::f32() - 0.5, fastrand::f32() - 0.5, 0.)
+impl Sensor for Camera {
+    fn connect(&self, p: Point3) -> Option<SensorConnection> {
+        let d = p - self.center;
+        let depth = -d.dot(&self.w);
+        if depth <= 0. {
+            return None;
+        }
+
+        // Where the ray through `p` crosses the viewport.
+        let viewport_upper_left = self.pixel00_loc - 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        let on_viewport = self.center + (self.focus_dist / depth) * d - viewport_upper_left;
+        let x = on_viewport.dot(&self.pixel_delta_u) / self.pixel_delta_u.len_square();
+        let y = on_viewport.dot(&self.pixel_delta_v) / self.pixel_delta_v.len_square();
+        if x < 0. || y < 0. || x >= self.image_width as Precision || y >= self.image_height as Precision {
+            return None;
+        }
+
+        let cos = depth / d.len();
+        let pixel_area = self.pixel_delta_u.len() * self.pixel_delta_v.len();
+        let importance = self.focus_dist * self.focus_dist / (pixel_area * cos * cos * cos);
+
+        Some(SensorConnection {
+            origin: self.center,
+            x,
+            y,
+            importance,
+        })
     }
 
-    /// Returns a random point in the camera defocus disk.
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
-        self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
+    fn pdf(&self, direction: &Vec3) -> Precision {
+        let cos = -direction.unit_vec().dot(&self.w);
+        if cos <= 0. {
+            return 0.;
+        }
+
+        let image_area = (self.image_width * self.image_height) as Precision
+            * self.pixel_delta_u.len()
+            * self.pixel_delta_v.len();
+        self.focus_dist * self.focus_dist / (image_area * cos * cos * cos)
+    }
+}
+
+/// A ray leaving the camera, and the weight of the radiance it carries back to the film.
+struct CameraRay {
+    ray: Ray,
+    weight: Color,
+}
+
+impl CameraRay {
+    fn unweighted(ray: Ray) -> Self {
+        Self {
+            ray,
+            weight: Color::new(1., 1., 1.),
+        }
     }
 }
 
@@ -267,3 +1217,570 @@ impl Default for Camera {
 pub fn lerp(start: Color, end: Color, progress: Precision) -> Color {
     (1.0 - progress) * start + progress * end
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        figures::sphere::Sphere,
+        integrators::{
+            bidirectional::BidirectionalPathTracer,
+            debug::{DebugIntegrator, DebugMode},
+            photon::{PhotonMapSettings, PhotonMapper},
+        },
+        materials::{dielectric::Dielectric, diffuse_light::DiffuseLight, lambertian::Lambertian},
+        render::{aov::Aov, progress::Progress, region::Region, stats::Counters},
+    };
+
+    use super::*;
+
+    fn small_camera(seed: u64) -> Camera {
+        let image_settings = ImageSettings {
+            image_width: 16,
+            samples_per_pixel: 4,
+            seed,
+            ..Default::default()
+        };
+
+        Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default())
+    }
+
+    /// Renders in passes of 3 samples, writing checkpoints to `checkpoint`.
+    fn progressive_camera(samples_per_pixel: i32, checkpoint: &Path) -> Camera {
+        let image_settings = ImageSettings {
+            image_width: 16,
+            samples_per_pixel,
+            progressive: Some(ProgressiveSettings {
+                samples_per_pass: 3,
+                checkpoint: Some(checkpoint.to_path_buf()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default())
+    }
+
+    fn small_world() -> Vec<Sphere> {
+        let material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        vec![
+            Sphere::new(Point3::new(0., 0., -1.), 0.5, material.clone()),
+            Sphere::new(Point3::new(0., -100.5, -1.), 100., material),
+        ]
+    }
+
+    #[test]
+    fn adaptive_sampling_skips_converged_pixels() {
+        let world = small_world();
+        let image_settings = ImageSettings {
+            image_width: 16,
+            samples_per_pixel: 64,
+            adaptive: Some(AdaptiveSettings::default()),
+            ..Default::default()
+        };
+        let camera = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+
+        let output = camera.render_output(&world);
+        let min = output.sample_counts.iter().copied().min().unwrap();
+        let max = output.sample_counts.iter().copied().max().unwrap();
+
+        // The sky converges straight away, the diffuse spheres do not.
+        assert_eq!(min, AdaptiveSettings::default().min_samples);
+        assert!(max > min);
+    }
+
+    #[test]
+    fn stereo_packs_both_eyes() {
+        let world = small_world();
+        let image_settings = ImageSettings {
+            image_width: 16,
+            samples_per_pixel: 1,
+            ..Default::default()
+        };
+        let stereo = StereoSettings {
+            interocular_distance: 0.2,
+            convergence_distance: 1.,
+            layout: StereoLayout::OverUnder,
+            ..Default::default()
+        };
+        let view_settings = ViewSettings {
+            stereo: Some(stereo),
+            ..Default::default()
+        };
+        let camera = Camera::new(image_settings, view_settings, DefocusSettings::default());
+
+        let image = camera.render_to_ppm(&world);
+        assert_eq!((image.cols(), image.rows()), (16, 18));
+
+        // Each half is its eye's view, and the eyes see the sphere from different sides.
+        let eyes = [Eye::Left, Eye::Right].map(|eye| camera.eye_camera(&stereo, eye));
+        let halves = eyes.each_ref().map(|eye| eye.render_to_ppm(&world));
+        for (half, eye) in halves.iter().enumerate() {
+            for (row, col) in (0..9).flat_map(|row| (0..16).map(move |col| (row, col))) {
+                assert_eq!(image.get(9 * half + row, col), eye.get(row, col));
+            }
+        }
+        assert_ne!(halves[0], halves[1]);
+
+        // The centers of both eye images look at the same point at the convergence distance.
+        let target = camera.look_from - stereo.convergence_distance * camera.w;
+        for eye in &eyes {
+            let image_center = eye.pixel00_loc
+                + (eye.image_width as Precision / 2. - 0.5) * eye.pixel_delta_u
+                + (eye.image_height as Precision / 2. - 0.5) * eye.pixel_delta_v;
+            let direction = image_center - eye.center;
+            let seen = eye.center + (stereo.convergence_distance / direction.dot(&-eye.w)) * direction;
+            assert!((seen - target).len() < 1e-4, "{seen:?} != {target:?}");
+        }
+    }
+
+    #[test]
+    fn region_matches_full_render() {
+        let world = small_world();
+        let render = |region: Option<RenderRegion>| {
+            let image_settings = ImageSettings {
+                image_width: 16,
+                samples_per_pixel: 2,
+                filter: Filter::mitchell(2.),
+                region,
+                ..Default::default()
+            };
+            Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default()).render_to_ppm(&world)
+        };
+
+        let full = render(None);
+        let cropped = render(Some(RenderRegion {
+            region: Region::Pixels {
+                col_min: 3,
+                row_min: 2,
+                col_max: 9,
+                row_max: 7,
+            },
+            output: RegionOutput::Cropped,
+        }));
+        let composited = render(Some(RenderRegion {
+            region: Region::Crop {
+                x_min: 0.5,
+                y_min: 0.,
+                x_max: 1.,
+                y_max: 0.5,
+            },
+            output: RegionOutput::Composited,
+        }));
+
+        assert_eq!((cropped.cols(), cropped.rows()), (6, 5));
+        for row in 0..5 {
+            for col in 0..6 {
+                assert_eq!(cropped.get(row, col), full.get(row + 2, col + 3));
+            }
+        }
+
+        assert_eq!((composited.cols(), composited.rows()), (16, 9));
+        assert_eq!(composited.get(0, 8), full.get(0, 8));
+        assert_eq!(composited.get(3, 15), full.get(3, 15));
+        assert_eq!(composited.get(0, 7), Color::new(0., 0., 0.));
+        assert_eq!(composited.get(5, 8), Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn resumed_render_matches_full_render() {
+        let world = small_world();
+        let checkpoint = std::env::temp_dir().join(format!("ray_tracing_resume_{}.ckpt", std::process::id()));
+
+        progressive_camera(4, &checkpoint).render_output(&world);
+        let resumed = progressive_camera(8, &checkpoint).resume(&world, &checkpoint);
+
+        // Samples taken with other settings do not mix in.
+        let mut blurred = progressive_camera(8, &checkpoint);
+        blurred.filter = Filter::gaussian(1.);
+        let fewer = progressive_camera(2, &checkpoint);
+        let (image_settings, mut view_settings, defocus_settings) = progressive_camera(8, &checkpoint).settings();
+        view_settings.look_from = Point3::new(0.5, 0., 0.);
+        let moved = Camera::new(image_settings, view_settings, defocus_settings);
+        let mismatched = [
+            blurred.resume(&world, &checkpoint),
+            fewer.resume(&world, &checkpoint),
+            moved.resume(&world, &checkpoint),
+        ];
+        std::fs::remove_file(&checkpoint).unwrap();
+
+        let resumed = resumed.unwrap();
+        let mut full = small_camera(0);
+        full.samples_per_pixel = 8;
+        assert_eq!(resumed.image, full.render_to_ppm(&world));
+        assert!(resumed.sample_counts.iter().all(|&count| count == 8));
+        for output in mismatched {
+            assert_eq!(output.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn resumed_render_denoises_like_full_render() {
+        let world = small_world();
+        let checkpoint = std::env::temp_dir().join(format!("ray_tracing_denoise_{}.ckpt", std::process::id()));
+        let camera = |samples_per_pixel| {
+            let mut camera = progressive_camera(samples_per_pixel, &checkpoint);
+            camera.denoise = Some(DenoiseSettings::default());
+            camera
+        };
+
+        camera(4).render_output(&world);
+        let resumed = camera(8).resume(&world, &checkpoint);
+        std::fs::remove_file(&checkpoint).unwrap();
+
+        // The albedo and normal guides carry on from the checkpoint.
+        let mut full = small_camera(0);
+        full.samples_per_pixel = 8;
+        full.denoise = Some(DenoiseSettings::default());
+        assert_eq!(resumed.unwrap().image, full.render_to_ppm(&world));
+    }
+
+    #[test]
+    fn same_seed_same_image() {
+        let world = small_world();
+
+        let image = small_camera(7).render_to_ppm(&world);
+        assert_eq!(image, small_camera(7).render_to_ppm(&world));
+
+        // Another seed gives other samples in every pixel, not just in a few of them.
+        let other = small_camera(8).render_to_ppm(&world);
+        let differing = image.values().iter().zip(other.values()).filter(|(a, b)| a != b).count();
+        assert_eq!(differing, image.values().len());
+    }
+
+    #[test]
+    fn stats_count_the_work_done() {
+        let world = small_world();
+        let mut camera = small_camera(7);
+        assert_eq!(camera.render_output(&world).stats.counters, Counters::default());
+
+        camera.stats = Some(StatsOutput::Summary);
+        let output = camera.render_output(&world);
+        let counters = output.stats.counters;
+
+        let samples: i32 = output.sample_counts.iter().sum();
+        assert_eq!(counters.camera_rays, samples as u64);
+        assert!(counters.secondary_rays > 0);
+        assert_eq!(counters.primitive_tests, counters.rays() * world.len() as u64);
+        assert!(counters.average_path_length() > 1.);
+        assert!(output.stats.to_json().contains(&format!("\"camera_rays\": {samples},")));
+    }
+
+    #[test]
+    fn light_passes_add_up_to_the_image() {
+        let world = small_world();
+        let image_settings = ImageSettings {
+            image_width: 16,
+            samples_per_pixel: 8,
+            aovs: Some(AovSettings::default()),
+            ..Default::default()
+        };
+        let camera = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+        let output = camera.render_output(&world);
+        let pass = |aov| output.aovs.iter().find(|image| image.aov == aov).unwrap();
+
+        let light_passes = [
+            Aov::Emission,
+            Aov::DirectDiffuse,
+            Aov::IndirectDiffuse,
+            Aov::DirectSpecular,
+            Aov::IndirectSpecular,
+        ];
+        for (i, pixel) in output.image.values().iter().enumerate() {
+            let mut sum = Color::default();
+            for aov in light_passes {
+                let v = &pass(aov).values[3 * i..3 * i + 3];
+                sum += Color::new(v[0], v[1], v[2]);
+            }
+            let expected = camera.display.apply(sum);
+            assert!((*pixel - expected).len() < 1e-4, "{pixel} != {expected}");
+        }
+
+        // The sky is not covered, the spheres are.
+        let alpha = &pass(Aov::Alpha).values;
+        let object_ids = &pass(Aov::ObjectId).values;
+        assert_eq!(alpha[0], 0.);
+        assert_eq!(alpha[alpha.len() - 1], 1.);
+        assert!(object_ids.contains(&1.) && object_ids.contains(&2.));
+        assert!(pass(Aov::MaterialId).values.iter().all(|&id| id <= 1.));
+        assert!(pass(Aov::Depth).values.iter().zip(alpha).all(|(&depth, &alpha)| (depth > 0.) == (alpha > 0.)));
+    }
+
+    #[test]
+    fn denoising_brings_the_image_closer_to_converged() {
+        let world = small_world();
+        let render = |samples_per_pixel, denoise| {
+            let image_settings = ImageSettings {
+                image_width: 64,
+                samples_per_pixel,
+                denoise,
+                ..Default::default()
+            };
+            Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default()).render_to_ppm(&world)
+        };
+        let error = |image: &PPM, reference: &PPM| -> Precision {
+            image.values().iter().zip(reference.values()).map(|(a, b)| (*a - *b).len_square()).sum()
+        };
+
+        let reference = render(256, None);
+        let noisy = render(4, None);
+        let denoised = render(4, Some(DenoiseSettings::default()));
+
+        assert!(error(&denoised, &reference) < 0.6 * error(&noisy, &reference));
+    }
+
+    #[test]
+    fn russian_roulette_cuts_dim_paths_short() {
+        let world = small_world();
+        let render = |russian_roulette| {
+            let image_settings = ImageSettings {
+                image_width: 16,
+                samples_per_pixel: 8,
+                russian_roulette,
+                stats: Some(StatsOutput::Summary),
+                ..Default::default()
+            };
+            let camera = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+            camera.render_output(&world).stats.counters
+        };
+
+        let full = render(None);
+        let cut = render(Some(RussianRoulette::default()));
+
+        assert_eq!(full.russian_roulette_terminations, 0);
+        assert!(cut.russian_roulette_terminations > 0);
+        assert!(cut.secondary_rays < full.secondary_rays);
+    }
+
+    #[test]
+    fn integrators_can_be_swapped() {
+        #[derive(Debug)]
+        struct Constant;
+
+        impl Integrator for Constant {
+            fn radiance(
+                &self,
+                _ray: &Ray,
+                _world: &dyn Hittable,
+                _sensor: Option<&dyn Sensor>,
+                _rng: &mut Rng,
+                path: &mut PathRecord,
+            ) -> Color {
+                path.rays = 1;
+                Color::new(0.25, 0.5, 0.75)
+            }
+        }
+
+        let image_settings = ImageSettings {
+            image_width: 16,
+            samples_per_pixel: 2,
+            integrator: Some(Rc::new(Constant)),
+            stats: Some(StatsOutput::Summary),
+            ..Default::default()
+        };
+        let camera = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+        let output = camera.render_output(&small_world());
+
+        assert!(output.image.values().iter().all(|&c| (c - Color::new(0.25, 0.5, 0.75)).len() < 1e-6));
+        assert_eq!(output.stats.counters.secondary_rays, 0);
+    }
+
+    #[test]
+    fn invalid_samples_are_dropped() {
+        #[derive(Debug)]
+        struct Broken;
+
+        impl Integrator for Broken {
+            fn radiance(
+                &self,
+                ray: &Ray,
+                _world: &dyn Hittable,
+                _sensor: Option<&dyn Sensor>,
+                _rng: &mut Rng,
+                path: &mut PathRecord,
+            ) -> Color {
+                path.rays = 1;
+                if ray.direction().x() < 0. {
+                    Color::new(Precision::NAN, 0., 0.)
+                } else if ray.direction().y() > 0. {
+                    Color::new(0., Precision::INFINITY, 1.)
+                } else {
+                    Color::new(0.5, 0.5, 0.5)
+                }
+            }
+        }
+
+        let image_settings = ImageSettings {
+            image_width: 16,
+            samples_per_pixel: 2,
+            integrator: Some(Rc::new(Broken)),
+            stats: Some(StatsOutput::Summary),
+            ..Default::default()
+        };
+        let camera = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+        let output = camera.render_output(&small_world());
+
+        // Pixels only keep their valid samples, without black ones dimming them.
+        let gray = Color::new(0.5, 0.5, 0.5);
+        assert!(output.image.values().iter().all(|&c| c == Color::new(0., 0., 0.) || (c - gray).len() < 1e-6));
+        assert!(output.image.values().contains(&gray));
+        assert!(output.sample_counts.iter().all(|&count| count == 2));
+        let counters = output.stats.counters;
+        assert!(counters.invalid_samples > counters.camera_rays / 2 && counters.invalid_samples < counters.camera_rays);
+    }
+
+    #[test]
+    fn light_paths_reach_the_film() {
+        // A glass ball focusing a small lamp onto the floor.
+        let lamp = Rc::new(Sphere::new(
+            Point3::new(0., 1.5, -1.5),
+            0.2,
+            Rc::new(DiffuseLight::new(Color::new(40., 40., 40.))),
+        ));
+        let world = vec![
+            lamp.clone(),
+            Rc::new(Sphere::new(Point3::new(0., 0.2, -1.5), 0.4, Rc::new(Dielectric::new(1.5)))),
+            Rc::new(Sphere::new(Point3::new(0., -100.5, -1.5), 100., Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+        ];
+
+        let render = |integrator: Rc<dyn Integrator>, samples_per_pixel| {
+            let image_settings = ImageSettings {
+                image_width: 16,
+                samples_per_pixel,
+                integrator: Some(integrator),
+                ..Default::default()
+            };
+            let camera = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+            let image = camera.render_to_ppm(&world);
+            image.values().iter().fold(Color::default(), |sum, &c| sum + c) / image.values().len() as Precision
+        };
+
+        let path_tracer = render(Rc::new(PathTracer { max_depth: 8, russian_roulette: None, clamp_indirect: None }), 512);
+        let bidirectional = render(Rc::new(BidirectionalPathTracer { max_depth: 8, lights: vec![lamp] }), 64);
+
+        assert!((bidirectional - path_tracer).len() < 0.01 * path_tracer.len(), "{bidirectional} != {path_tracer}");
+    }
+
+    #[test]
+    fn exposes_through_the_lens() {
+        let image_settings = ImageSettings {
+            aspect_ratio: 1.5,
+            image_width: 30,
+            ..Default::default()
+        };
+        let pinhole = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+        assert_eq!(pinhole.f_number(), None);
+        assert!(pinhole.with_physical_exposure(100., 0.01).is_err());
+
+        // A 50 mm lens at f/2.8, focused 10 m away.
+        let (image_settings, mut view_settings, mut defocus_settings) = small_camera(0).settings();
+        let image_settings = ImageSettings { aspect_ratio: 1.5, image_width: 30, ..image_settings };
+        view_settings.vfov = 2. * (12. / 50. as Precision).atan().to_degrees();
+        defocus_settings.focus_dist = 10.;
+        defocus_settings.defocus_angle = 2. * (0.025 / 2.8 / 10. as Precision).atan().to_degrees();
+        let camera = Camera::new(image_settings, view_settings, defocus_settings)
+            .with_physical_exposure(100., 0.01)
+            .unwrap();
+
+        let Exposure::Physical { f_number, .. } = camera.display.exposure else {
+            panic!("{:?}", camera.display.exposure);
+        };
+        assert!((f_number - 2.8).abs() < 0.01, "{f_number}");
+    }
+
+    #[test]
+    fn traced_sample_matches_the_render() {
+        let world = small_world();
+        let image_settings = ImageSettings {
+            image_width: 16,
+            samples_per_pixel: 1,
+            filter: Filter::Box { radius: 0.5 },
+            ..Default::default()
+        };
+        let camera = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+        let image = camera.render_to_ppm(&world);
+
+        // Looking down at the floor.
+        let trace = camera.trace_pixel(&world, 7, 3, 0);
+        assert!((trace.color - image.values()[7 * 16 + 3]).len() < 1e-6);
+        assert!(!trace.bounces.is_empty());
+        assert_eq!(trace.bounces.len(), trace.rays as usize - 1);
+        assert_eq!(trace.termination, Termination::Escaped);
+
+        let mut throughput = Color::new(1., 1., 1.);
+        for bounce in &trace.bounces {
+            let scattered = bounce.scattered.unwrap();
+            assert!(scattered.pdf > 0.);
+            throughput *= scattered.attenuation;
+            assert_eq!(bounce.throughput, throughput);
+        }
+
+        assert!(trace.to_string().contains("Material:    Lambertian"));
+        assert!(trace.to_json().contains("\"termination\": \"Escaped\""));
+    }
+
+    #[test]
+    fn traces_the_camera_path_of_every_integrator() {
+        let lamp = Rc::new(Sphere::new(
+            Point3::new(0., 1.5, -1.),
+            0.3,
+            Rc::new(DiffuseLight::new(Color::new(10., 10., 10.))),
+        ));
+        let world = vec![
+            lamp.clone(),
+            Rc::new(Sphere::new(Point3::new(0., -100.5, -1.), 100., Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+        ];
+        let settings = PhotonMapSettings {
+            photons: 2_000,
+            ..Default::default()
+        };
+
+        // Light paths of the bidirectional path tracer land on other pixels too.
+        let integrators: Vec<(Rc<dyn Integrator>, bool)> = vec![
+            (Rc::new(BidirectionalPathTracer { max_depth: 5, lights: vec![lamp.clone()] }), false),
+            (Rc::new(PhotonMapper::new(vec![lamp], settings)), true),
+            (Rc::new(DebugIntegrator::new(DebugMode::Normal)), true),
+        ];
+        for (integrator, matches_pixel) in integrators {
+            let image_settings = ImageSettings {
+                image_width: 16,
+                samples_per_pixel: 1,
+                filter: Filter::Box { radius: 0.5 },
+                integrator: Some(integrator.clone()),
+                ..Default::default()
+            };
+            let camera = Camera::new(image_settings, ViewSettings::default(), DefocusSettings::default());
+            let image = camera.render_to_ppm(&world);
+
+            // Tracing leaves the photon map of the render in place.
+            let trace = camera.trace_pixel(&world, 7, 3, 0);
+            assert_eq!(camera.trace_pixel(&world, 7, 3, 0), trace);
+            assert!(!trace.bounces.is_empty(), "{integrator:?}");
+            if matches_pixel {
+                assert!((trace.color - image.values()[7 * 16 + 3]).len() < 1e-6, "{integrator:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn cancelling_stops_the_render() {
+        let world = small_world();
+        let camera = small_camera(7);
+        let cancel = CancellationToken::default();
+
+        // Cancel halfway through the image.
+        let mut reports = 0;
+        let mut observer = |progress: &Progress| {
+            reports += 1;
+            if progress.fraction >= 0.5 {
+                cancel.cancel();
+            }
+        };
+        let output = camera.render_with(&world, &mut observer, &cancel);
+
+        assert!(output.cancelled);
+        assert!(reports < camera.image_height);
+        assert!(output.sample_counts.contains(&0));
+        assert!(output.sample_counts.contains(&camera.samples_per_pixel));
+        assert!(!camera.render_output(&world).cancelled);
+    }
+}
@@ -16,6 +16,11 @@ pub struct HitRecord {
     pub material: Rc<dyn Material>,
     pub t: Precision,
     pub front_face: bool,
+    /// Surface coordinates of the hit point, both in `[0, 1]`.
+    pub u: Precision,
+    pub v: Precision,
+    /// Index of the hit object in the outermost list of objects.
+    pub object_id: u32,
 }
 
 impl HitRecord {
@@ -41,6 +46,9 @@ impl Default for HitRecord {
             material: Rc::new(material::default_material()),
             t: Default::default(),
             front_face: Default::default(),
+            u: Default::default(),
+            v: Default::default(),
+            object_id: Default::default(),
         }
     }
 }
@@ -55,11 +63,12 @@ impl<T: Hittable> Hittable for Vec<T> {
         let mut hit_anything = false;
         let mut closest_so_far = ray_t.max;
 
-        for item in self.iter() {
+        for (i, item) in self.iter().enumerate() {
             if item.hit(r, Interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
                 *rec = temp_rec.clone();
+                rec.object_id = i as u32;
             }
         }
 
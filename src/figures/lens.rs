@@ -0,0 +1,161 @@
+use std::{fmt::Debug, rc::Rc};
+
+use fastrand::Rng;
+
+use crate::{
+    image_formats::ppm::PPM,
+    utility::{
+        utils::{degrees_to_radians, pi32},
+        vec3::{Precision, Vec3},
+    },
+};
+
+/// Shape of the lens aperture, which is also the shape of out of focus highlights.
+#[derive(Debug, Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circular,
+    /// Regular polygon formed by `blades` straight diaphragm blades, rotated by
+    /// `rotation` degrees.
+    Polygonal { blades: u32, rotation: Precision },
+    /// Arbitrary shape given by an image.
+    Mask(Rc<ApertureMask>),
+}
+
+impl Aperture {
+    /// Random point on the aperture, in the `[-1, 1]` square around the lens center.
+    pub fn sample(&self, rng: &mut Rng) -> Vec3 {
+        match self {
+            Aperture::Circular => Vec3::random_in_unit_disk(rng),
+            Aperture::Polygonal { blades, rotation } => {
+                let blades = (*blades).max(3);
+                let step = 2. * pi32 / blades as Precision;
+                let start = degrees_to_radians(*rotation) + step * rng.u32(0..blades) as Precision;
+                let a = Vec3::new(start.cos(), start.sin(), 0.);
+                let b = Vec3::new((start + step).cos(), (start + step).sin(), 0.);
+
+                // Uniform point in the triangle between the center and one edge.
+                let (mut s, mut t) = (rng.f32(), rng.f32());
+                if s + t > 1. {
+                    (s, t) = (1. - s, 1. - t);
+                }
+                s * a + t * b
+            }
+            Aperture::Mask(mask) => mask.sample(rng),
+        }
+    }
+}
+
+/// Aperture shape taken from an image. Brighter pixels let more light through.
+#[derive(Clone)]
+pub struct ApertureMask {
+    cols: usize,
+    rows: usize,
+    // Cumulative pixel transmission in row major order, normalized to end at 1.
+    cdf: Vec<Precision>,
+}
+
+impl ApertureMask {
+    /// Builds a mask from the luminance of an image, stretched over the aperture.
+    ///
+    /// # Panics
+    ///
+    /// If the image is completely black.
+    pub fn from_ppm(image: &PPM) -> Self {
+        let mut total = 0.;
+        let mut cdf: Vec<Precision> = image
+            .values()
+            .iter()
+            .map(|c| {
+                total += c.luminance().max(0.);
+                total
+            })
+            .collect();
+
+        assert!(total > 0., "aperture mask lets no light through");
+        cdf.iter_mut().for_each(|v| *v /= total);
+
+        Self {
+            cols: image.cols(),
+            rows: image.rows(),
+            cdf,
+        }
+    }
+
+    pub fn sample(&self, rng: &mut Rng) -> Vec3 {
+        let u = rng.f32();
+        let index = self.cdf.partition_point(|&v| v <= u).min(self.cdf.len() - 1);
+        let (row, col) = (index / self.cols, index % self.cols);
+
+        let x = (col as Precision + rng.f32()) / self.cols as Precision;
+        let y = (row as Precision + rng.f32()) / self.rows as Precision;
+        Vec3::new(2. * x - 1., 1. - 2. * y, 0.)
+    }
+}
+
+impl Debug for ApertureMask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApertureMask")
+            .field("cols", &self.cols)
+            .field("rows", &self.rows)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Tilt-shift lens movements.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TiltShift {
+    /// Rotation of the plane of focus around the camera's horizontal axis, in degrees.
+    pub tilt: Precision,
+    /// Rotation of the plane of focus around the camera's vertical axis, in degrees.
+    pub swing: Precision,
+    /// Sideways shift of the lens, as a fraction of the image width.
+    pub shift_x: Precision,
+    /// Upwards shift of the lens, as a fraction of the image height.
+    pub shift_y: Precision,
+}
+
+impl TiltShift {
+    pub fn is_tilted(&self) -> bool {
+        self.tilt != 0. || self.swing != 0.
+    }
+}
+
+/// Rotates `v` by `degrees` around the unit vector `axis`.
+pub fn rotate(v: Vec3, axis: Vec3, degrees: Precision) -> Vec3 {
+    let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+    v * cos + axis.cross(&v) * sin + axis * axis.dot(&v) * (1. - cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utility::color::Color;
+
+    use super::*;
+
+    #[test]
+    fn polygon_samples_stay_inside() {
+        let mut rng = Rng::with_seed(1);
+        let aperture = Aperture::Polygonal { blades: 4, rotation: 0. };
+
+        for _ in 0..1000 {
+            // A square with its corners on the axes.
+            let p = aperture.sample(&mut rng);
+            assert!(p.x().abs() + p.y().abs() <= 1. + 1e-5, "{p}");
+        }
+    }
+
+    #[test]
+    fn mask_samples_skip_black_pixels() {
+        let mut rng = Rng::with_seed(1);
+        let black = Color::new(0., 0., 0.);
+        let white = Color::new(1., 1., 1.);
+        // Only the top right quadrant is open.
+        let mask = ApertureMask::from_ppm(&PPM::new(2, 2, 255, vec![black, white, black, black]));
+
+        for _ in 0..1000 {
+            let p = mask.sample(&mut rng);
+            assert!(p.x() >= 0. && p.y() >= 0., "{p}");
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use std::{fs, io, path::Path, rc::Rc};
+
+use fastrand::Rng;
+
+use crate::utility::{
+    color::Color,
+    ray::Ray,
+    vec3::{Point3, Precision, Vec3},
+};
+
+/// One refracting surface of a lens, or the aperture stop, as listed in a lens
+/// prescription from the front of the lens to the back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    /// Radius of curvature in mm, positive when the center of curvature is towards the
+    /// film. 0 marks the aperture stop.
+    pub curvature_radius: Precision,
+    /// Distance along the axis to the next surface towards the film, in mm.
+    pub thickness: Precision,
+    /// Index of refraction at the d line (587.6 nm) of the medium behind the surface.
+    pub ior: Precision,
+    /// Diameter of the surface, in mm.
+    pub aperture_diameter: Precision,
+    /// Abbe number of the medium behind the surface, if it disperses light.
+    pub abbe_number: Option<Precision>,
+}
+
+impl LensElement {
+    pub fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.
+    }
+}
+
+/// Lens design given as a table of surfaces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LensPrescription {
+    pub elements: Vec<LensElement>,
+}
+
+impl LensPrescription {
+    /// Reads a prescription table from a file, see `LensPrescription::parse`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        LensPrescription::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a prescription table with one surface per line, from the front of the lens
+    /// to the back:
+    ///
+    /// ```text
+    /// # radius  thickness  ior  aperture  [abbe]
+    /// 29.475    3.76       1.67 25.2
+    /// 0         4.5        0    17.1
+    /// ```
+    ///
+    /// All lengths are in mm. An index of refraction of 0 stands for air, and the last
+    /// thickness is replaced when the lens is focused.
+    pub fn parse(table: &str) -> io::Result<Self> {
+        let mut elements = Vec::new();
+
+        for line in table.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<Precision>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid_data(line))?;
+            if !(4..=5).contains(&values.len()) {
+                return Err(invalid_data(line));
+            }
+
+            elements.push(LensElement {
+                curvature_radius: values[0],
+                thickness: values[1],
+                ior: if values[2] == 0. { 1. } else { values[2] },
+                aperture_diameter: values[3],
+                abbe_number: values.get(4).copied(),
+            });
+        }
+
+        if elements.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty lens prescription"));
+        }
+
+        Ok(Self { elements })
+    }
+}
+
+fn invalid_data(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid lens prescription line: {line}"),
+    )
+}
+
+fn invalid_lens(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Settings for a camera that traces rays through a real lens instead of a thin lens.
+#[derive(Debug, Clone)]
+pub struct RealisticLens {
+    pub prescription: Rc<LensPrescription>,
+    /// Diagonal of the film, in mm.
+    pub film_diagonal: Precision,
+    /// Scene units per mm of the prescription, 0.001 for scenes modelled in meters.
+    pub units_per_mm: Precision,
+}
+
+// Fraunhofer C, d and F lines, in µm, used for the red, green and blue channels.
+const WAVELENGTHS: [Precision; 3] = [0.6563, 0.5876, 0.4861];
+
+const EXIT_PUPIL_BINS: usize = 64;
+const EXIT_PUPIL_SAMPLES: usize = 64;
+
+/// A lens surface scaled to scene units.
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    curvature_radius: Precision,
+    thickness: Precision,
+    aperture_radius: Precision,
+    // Index of refraction behind the surface, for each color channel.
+    iors: [Precision; 3],
+}
+
+impl Interface {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.
+    }
+}
+
+/// Axis aligned rectangle on the plane of the rear lens surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    min: (Precision, Precision),
+    max: (Precision, Precision),
+}
+
+impl Bounds {
+    fn area(&self) -> Precision {
+        (self.max.0 - self.min.0) * (self.max.1 - self.min.1)
+    }
+}
+
+/// A lens prescription focused at a distance, ready to generate camera rays.
+///
+/// Rays are in camera space, with the film centered at the origin, x to the right, y up
+/// and the lens looking down -z.
+#[derive(Debug, Clone)]
+pub struct LensSystem {
+    interfaces: Vec<Interface>,
+    film_width: Precision,
+    film_height: Precision,
+    dispersive: bool,
+    // Bounds of the rays leaving the rear surface, by distance of the film point from
+    // the axis, for film points on the +x axis.
+    exit_pupil_bounds: Vec<Bounds>,
+}
+
+impl LensSystem {
+    /// Focuses `lens` at `focus_distance`, failing if the lens blocks the rays near its
+    /// axis or cannot focus that close.
+    pub fn new(lens: &RealisticLens, focus_distance: Precision, aspect_ratio: Precision) -> io::Result<Self> {
+        if lens.prescription.elements.is_empty() {
+            return Err(invalid_lens("the lens prescription has no surfaces".to_string()));
+        }
+
+        let interfaces = lens
+            .prescription
+            .elements
+            .iter()
+            .map(|element| Interface {
+                curvature_radius: element.curvature_radius * lens.units_per_mm,
+                thickness: element.thickness * lens.units_per_mm,
+                aperture_radius: element.aperture_diameter * lens.units_per_mm / 2.,
+                iors: dispersed_iors(element.ior, element.abbe_number),
+            })
+            .collect();
+
+        let film_diagonal = lens.film_diagonal * lens.units_per_mm;
+        let film_height = film_diagonal / (1. + aspect_ratio * aspect_ratio).sqrt();
+
+        let mut system = Self {
+            interfaces,
+            film_width: film_height * aspect_ratio,
+            film_height,
+            dispersive: lens.prescription.elements.iter().any(|e| e.abbe_number.is_some()),
+            exit_pupil_bounds: Vec::new(),
+        };
+
+        let back_focus = system.focus_thick_lens(focus_distance)?;
+        system.interfaces.last_mut().unwrap().thickness = back_focus;
+        system.exit_pupil_bounds = (0..EXIT_PUPIL_BINS)
+            .map(|i| {
+                let r0 = i as Precision / EXIT_PUPIL_BINS as Precision * film_diagonal / 2.;
+                let r1 = (i + 1) as Precision / EXIT_PUPIL_BINS as Precision * film_diagonal / 2.;
+                system.bound_exit_pupil(r0, r1)
+            })
+            .collect();
+
+        Ok(system)
+    }
+
+    /// Camera space ray through the lens for the image position `s`, `t`, both in
+    /// `[0, 1]` from the top left corner, and the weight of its radiance.
+    ///
+    /// Returns `None` if the ray is blocked inside the lens.
+    pub fn generate_ray(&self, s: Precision, t: Precision, rng: &mut Rng) -> Option<(Ray, Color)> {
+        // The lens flips the image, so the top left of the image is at the bottom right
+        // of the film.
+        let film = Point3::new(
+            (1. - 2. * s) * self.film_width / 2.,
+            (2. * t - 1.) * self.film_height / 2.,
+            0.,
+        );
+        let (rear, bounds_area) = self.sample_exit_pupil(film, rng.f32(), rng.f32());
+
+        let channel = if self.dispersive { rng.usize(0..3) } else { 1 };
+        let film_ray = Ray::new(film, rear - film);
+        let ray = self.trace_from_film(&film_ray, channel)?;
+
+        // Irradiance falloff on the film, relative to the exit pupil on the axis.
+        let cos_theta = film_ray.direction().unit_vec().z().abs();
+        let weight = cos_theta.powi(4) * bounds_area / self.exit_pupil_bounds[0].area();
+
+        let weight = if self.dispersive {
+            let mut channels = Color::default();
+            channels[channel as i32] = 3. * weight;
+            channels
+        } else {
+            Color::new(weight, weight, weight)
+        };
+
+        Some((Ray::new(*ray.origin(), ray.direction().unit_vec()), weight))
+    }
+
+    /// Focal length over the diameter of the entrance pupil, found by tracing rays parallel
+    /// to the axis in from the scene.
+    pub fn f_number(&self) -> Option<Precision> {
+        let (pz, fz) = self.thick_lens_approximation().ok()?;
+        let passes = |x| {
+            let ray = Ray::new(Point3::new(x, 0., -(self.front_z() + 1.)), Vec3::new(0., 0., 1.));
+            self.trace_from_scene(&ray, 1).is_some()
+        };
+
+        // Bisect for the widest ray that gets through.
+        let (mut inside, mut outside) = (0., self.interfaces[0].aperture_radius);
+        for _ in 0..32 {
+            let x = 0.5 * (inside + outside);
+            if passes(x) {
+                inside = x;
+            } else {
+                outside = x;
+            }
+        }
+
+        (inside > 0.).then(|| (fz[0] - pz[0]).abs() / (2. * inside))
+    }
+
+    fn front_z(&self) -> Precision {
+        self.interfaces.iter().map(|i| i.thickness).sum()
+    }
+
+    fn rear_z(&self) -> Precision {
+        self.interfaces.last().unwrap().thickness
+    }
+
+    fn rear_radius(&self) -> Precision {
+        self.interfaces.last().unwrap().aperture_radius
+    }
+
+    /// Traces a ray starting on the film side out of the front of the lens.
+    fn trace_from_film(&self, ray: &Ray, channel: usize) -> Option<Ray> {
+        let mut ray = ray.clone();
+        let mut element_z = 0.;
+
+        for (i, interface) in self.interfaces.iter().enumerate().rev() {
+            element_z -= interface.thickness;
+            self.intersect_interface(interface, element_z, &mut ray)?;
+
+            if !interface.is_stop() {
+                let eta_i = interface.iors[channel];
+                let eta_t = if i > 0 { self.interfaces[i - 1].iors[channel] } else { 1. };
+                self.refract_at_interface(interface, element_z, &mut ray, eta_i / eta_t)?;
+            }
+        }
+
+        Some(ray)
+    }
+
+    /// Traces a ray starting on the scene side out of the back of the lens.
+    fn trace_from_scene(&self, ray: &Ray, channel: usize) -> Option<Ray> {
+        let mut ray = ray.clone();
+        let mut element_z = -self.front_z();
+
+        for (i, interface) in self.interfaces.iter().enumerate() {
+            self.intersect_interface(interface, element_z, &mut ray)?;
+
+            if !interface.is_stop() {
+                let eta_i = if i > 0 { self.interfaces[i - 1].iors[channel] } else { 1. };
+                let eta_t = interface.iors[channel];
+                self.refract_at_interface(interface, element_z, &mut ray, eta_i / eta_t)?;
+            }
+
+            element_z += interface.thickness;
+        }
+
+        Some(ray)
+    }
+
+    /// Moves the ray origin to where it crosses the interface whose vertex is at
+    /// `element_z`, failing if it misses or is blocked by the interface's rim.
+    fn intersect_interface(&self, interface: &Interface, element_z: Precision, ray: &mut Ray) -> Option<()> {
+        let t = if interface.is_stop() {
+            if ray.direction().z() == 0. {
+                return None;
+            }
+            (element_z - ray.origin().z()) / ray.direction().z()
+        } else {
+            let center = Point3::new(0., 0., element_z + interface.curvature_radius);
+            intersect_spherical_element(interface.curvature_radius, center, ray)?
+        };
+
+        if t <= 0. {
+            return None;
+        }
+
+        let hit = ray.at(t);
+        if hit.x().powi(2) + hit.y().powi(2) > interface.aperture_radius.powi(2) {
+            return None;
+        }
+
+        *ray.origin_mut() = hit;
+        Some(())
+    }
+
+    fn refract_at_interface(&self, interface: &Interface, element_z: Precision, ray: &mut Ray, eta: Precision) -> Option<()> {
+        let center = Point3::new(0., 0., element_z + interface.curvature_radius);
+        let wi = -ray.direction().unit_vec();
+        let normal = (*ray.origin() - center).unit_vec();
+        let normal = if normal.dot(&wi) < 0. { -normal } else { normal };
+
+        *ray.direction_mut() = refract(wi, normal, eta)?;
+        Some(())
+    }
+
+    /// Principal and focal plane positions of a thick lens approximating the system, for
+    /// rays entering from the scene and from the film side.
+    fn thick_lens_approximation(&self) -> io::Result<([Precision; 2], [Precision; 2])> {
+        let x = 0.001 * (self.film_width.powi(2) + self.film_height.powi(2)).sqrt();
+        let blocked = |side| invalid_lens(format!("rays from the {side} near the axis are blocked by the lens"));
+
+        let scene_ray = Ray::new(Point3::new(x, 0., -(self.front_z() + 1.)), Vec3::new(0., 0., 1.));
+        let film_ray = self.trace_from_scene(&scene_ray, 1).ok_or_else(|| blocked("scene"))?;
+        let (pz0, fz0) = cardinal_points(&scene_ray, &film_ray);
+
+        let film_ray = Ray::new(Point3::new(x, 0., 1. - self.rear_z()), Vec3::new(0., 0., -1.));
+        let scene_ray = self.trace_from_film(&film_ray, 1).ok_or_else(|| blocked("film"))?;
+        let (pz1, fz1) = cardinal_points(&film_ray, &scene_ray);
+
+        Ok(([pz0, pz1], [fz0, fz1]))
+    }
+
+    /// Distance from the rear surface to the film that brings `focus_distance` into focus.
+    fn focus_thick_lens(&self, focus_distance: Precision) -> io::Result<Precision> {
+        let (pz, fz) = self.thick_lens_approximation()?;
+        let f = fz[0] - pz[0];
+        let z = -focus_distance;
+
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4. * f - pz[0]);
+        if c.is_nan() || c <= 0. {
+            return Err(invalid_lens(format!("focus distance {focus_distance} is too short for the lens")));
+        }
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+
+        Ok(self.rear_z() + delta)
+    }
+
+    /// Bounds of the points on the rear surface plane that rays from film points at
+    /// distance `[r0, r1]` from the axis get through the lens from.
+    fn bound_exit_pupil(&self, r0: Precision, r1: Precision) -> Bounds {
+        let rear_z = -self.rear_z();
+        let extent = 1.5 * self.rear_radius();
+        let projected = Bounds {
+            min: (-extent, -extent),
+            max: (extent, extent),
+        };
+
+        let samples = EXIT_PUPIL_SAMPLES * EXIT_PUPIL_SAMPLES;
+        let mut bounds: Option<Bounds> = None;
+
+        for i in 0..samples {
+            let film_x = r0 + (r1 - r0) * (i as Precision + 0.5) / samples as Precision;
+            let u = ((i % EXIT_PUPIL_SAMPLES) as Precision + 0.5) / EXIT_PUPIL_SAMPLES as Precision;
+            let v = ((i / EXIT_PUPIL_SAMPLES) as Precision + 0.5) / EXIT_PUPIL_SAMPLES as Precision;
+            let rear = (-extent + 2. * extent * u, -extent + 2. * extent * v);
+
+            let film = Point3::new(film_x, 0., 0.);
+            let ray = Ray::new(film, Point3::new(rear.0, rear.1, rear_z) - film);
+            if self.trace_from_film(&ray, 1).is_some() {
+                bounds = Some(match bounds {
+                    None => Bounds { min: rear, max: rear },
+                    Some(b) => Bounds {
+                        min: (b.min.0.min(rear.0), b.min.1.min(rear.1)),
+                        max: (b.max.0.max(rear.0), b.max.1.max(rear.1)),
+                    },
+                });
+            }
+        }
+
+        let Some(bounds) = bounds else {
+            return projected;
+        };
+
+        // Grow by the sample spacing so the gaps between samples are covered.
+        let margin = 2. * extent / EXIT_PUPIL_SAMPLES as Precision;
+        Bounds {
+            min: (bounds.min.0 - margin, bounds.min.1 - margin),
+            max: (bounds.max.0 + margin, bounds.max.1 + margin),
+        }
+    }
+
+    /// Point on the rear surface plane to aim a ray from `film` at, and the area of the
+    /// bounds it was sampled from.
+    fn sample_exit_pupil(&self, film: Point3, u: Precision, v: Precision) -> (Point3, Precision) {
+        let r_film = (film.x().powi(2) + film.y().powi(2)).sqrt();
+        let half_diagonal = (self.film_width.powi(2) + self.film_height.powi(2)).sqrt() / 2.;
+        let index = ((r_film / half_diagonal * EXIT_PUPIL_BINS as Precision) as usize).min(EXIT_PUPIL_BINS - 1);
+        let bounds = self.exit_pupil_bounds[index];
+
+        let x = bounds.min.0 + (bounds.max.0 - bounds.min.0) * u;
+        let y = bounds.min.1 + (bounds.max.1 - bounds.min.1) * v;
+
+        // The bounds were computed on the +x axis, rotate them to the film point.
+        let (sin, cos) = if r_film != 0. {
+            (film.y() / r_film, film.x() / r_film)
+        } else {
+            (0., 1.)
+        };
+
+        (Point3::new(cos * x - sin * y, sin * x + cos * y, -self.rear_z()), bounds.area())
+    }
+}
+
+/// Indices of refraction for the red, green and blue channels, following Cauchy's
+/// equation fitted to the d line index and the Abbe number.
+fn dispersed_iors(ior: Precision, abbe_number: Option<Precision>) -> [Precision; 3] {
+    let Some(abbe_number) = abbe_number else {
+        return [ior; 3];
+    };
+
+    let [c, d, f] = WAVELENGTHS;
+    let b = (ior - 1.) / abbe_number / (1. / (f * f) - 1. / (c * c));
+    let a = ior - b / (d * d);
+
+    WAVELENGTHS.map(|wavelength| a + b / (wavelength * wavelength))
+}
+
+fn intersect_spherical_element(radius: Precision, center: Point3, ray: &Ray) -> Option<Precision> {
+    let o = *ray.origin() - center;
+    let d = *ray.direction();
+    let a = d.len_square();
+    let b = 2. * d.dot(&o);
+    let c = o.len_square() - radius * radius;
+
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    let t0 = (-b - sqrtd) / (2. * a);
+    let t1 = (-b + sqrtd) / (2. * a);
+
+    // Pick the intersection on the side of the sphere facing the vertex.
+    let use_closer = (d.z() > 0.) ^ (radius < 0.);
+    Some(if use_closer { t0.min(t1) } else { t0.max(t1) })
+}
+
+/// Refracts the direction `wi`, pointing away from the surface on the same side as
+/// `normal`, with `eta` the ratio of the indices of refraction. `None` on total internal
+/// reflection.
+fn refract(wi: Vec3, normal: Vec3, eta: Precision) -> Option<Vec3> {
+    let cos_theta_i = normal.dot(&wi);
+    let sin2_theta_i = (1. - cos_theta_i * cos_theta_i).max(0.);
+    let sin2_theta_t = eta * eta * sin2_theta_i;
+    if sin2_theta_t >= 1. {
+        return None;
+    }
+
+    let cos_theta_t = (1. - sin2_theta_t).sqrt();
+    Some(eta * -wi + (eta * cos_theta_i - cos_theta_t) * normal)
+}
+
+/// Axial positions of the principal and focal planes from a ray parallel to the axis
+/// entering the lens and the same ray leaving it.
+fn cardinal_points(r_in: &Ray, r_out: &Ray) -> (Precision, Precision) {
+    let tf = -r_out.origin().x() / r_out.direction().x();
+    let fz = r_out.at(tf).z();
+    let tp = (r_in.origin().x() - r_out.origin().x()) / r_out.direction().x();
+    let pz = r_out.at(tp).z();
+
+    (pz, fz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Double Gauss, US patent 2,673,491, scaled to a 50 mm focal length.
+    const DOUBLE_GAUSS: &str = "
+        # radius  thickness  ior    aperture
+        29.475    3.76       1.67   25.2
+        84.83     0.12       1      25.2
+        19.275    4.025      1.67   23
+        40.77     3.275      1.699  23
+        12.75     5.705      1      18
+        0         4.5        0      17.1   # aperture stop
+        -14.495   1.18       1.603  17
+        40.77     6.065      1.658  20
+        -20.385   0.19       1      20
+        437.065   3.22       1.717  20
+        -39.73    0          1      20
+    ";
+
+    fn double_gauss() -> RealisticLens {
+        RealisticLens {
+            prescription: Rc::new(LensPrescription::parse(DOUBLE_GAUSS).unwrap()),
+            film_diagonal: 35.,
+            units_per_mm: 0.001,
+        }
+    }
+
+    #[test]
+    fn double_gauss_focal_length() {
+        let system = LensSystem::new(&double_gauss(), 10., 1.5).unwrap();
+        let (pz, fz) = system.thick_lens_approximation().unwrap();
+
+        assert!(((fz[0] - pz[0]).abs() - 0.05).abs() < 0.002, "{pz:?} {fz:?}");
+        assert!(system.rear_z() > 0.03 && system.rear_z() < 0.05, "{}", system.rear_z());
+
+        let f_number = system.f_number().unwrap();
+        assert!(f_number > 1.5 && f_number < 3., "{f_number}");
+    }
+
+    #[test]
+    fn center_ray_leaves_along_the_axis() {
+        let system = LensSystem::new(&double_gauss(), 10., 1.5).unwrap();
+        let mut rng = Rng::with_seed(1);
+
+        let mut traced = 0;
+        for _ in 0..100 {
+            if let Some((ray, weight)) = system.generate_ray(0.5, 0.5, &mut rng) {
+                // Rays from the film center converge 10 m in front of the film.
+                let t = (-10. - ray.origin().z()) / ray.direction().z();
+                let p = ray.at(t);
+                assert!(p.x().abs() < 0.05 && p.y().abs() < 0.05, "{p}");
+                assert!(weight.x() > 0.);
+                traced += 1;
+            }
+        }
+
+        assert!(traced > 50);
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        assert!(LensPrescription::parse("1 2 3").is_err());
+        assert!(LensPrescription::parse("a b c d").is_err());
+        assert!(LensPrescription::parse("# nothing").is_err());
+    }
+
+    #[test]
+    fn rejects_focus_closer_than_the_lens_allows() {
+        let err = LensSystem::new(&double_gauss(), 0.01, 1.5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("too short"), "{err}");
+    }
+}
@@ -1,3 +1,7 @@
 pub mod hittable;
 pub mod sphere;
 pub mod camera;
+pub mod projection;
+pub mod stereo;
+pub mod lens;
+pub mod lens_system;
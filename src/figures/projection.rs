@@ -0,0 +1,111 @@
+use crate::utility::{
+    ray::Ray,
+    utils::{degrees_to_radians, pi32},
+    vec3::{Point3, Precision, Vec3},
+};
+
+/// How image positions map to camera rays.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    /// Pinhole or thin lens perspective, with the field of view from `ViewSettings::vfov`.
+    #[default]
+    Perspective,
+    /// Parallel rays, `view_height` is the height of the view in world units.
+    Orthographic { view_height: Precision },
+    /// Equidistant circular fisheye inscribed in the image height. `fov` is the angle
+    /// across the circle in degrees and may exceed 180.
+    Fisheye { fov: Precision },
+    /// Full 360 by 180 degree latitude-longitude panorama.
+    Equirectangular,
+    /// 360 degree horizontal panorama with a perspective vertical axis.
+    Cylindrical { vfov: Precision },
+}
+
+impl Projection {
+    /// Ray in camera space for the image position `s`, `t`, both in `[0, 1]` from the top
+    /// left corner. Camera space has x to the right, y up and looks down -z.
+    ///
+    /// Returns `None` for positions that see nothing, like the corners of a fisheye image.
+    /// Perspective rays are built by the camera itself since they depend on its lens.
+    pub fn camera_space_ray(&self, s: Precision, t: Precision, aspect_ratio: Precision) -> Option<Ray> {
+        let origin = Point3::default();
+
+        match *self {
+            Projection::Perspective => None,
+            Projection::Orthographic { view_height } => {
+                let x = (s - 0.5) * view_height * aspect_ratio;
+                let y = (0.5 - t) * view_height;
+                Some(Ray::new(Point3::new(x, y, 0.), Vec3::new(0., 0., -1.)))
+            }
+            Projection::Fisheye { fov } => {
+                let x = (2. * s - 1.) * aspect_ratio;
+                let y = 1. - 2. * t;
+                let r = (x * x + y * y).sqrt();
+                if r > 1. {
+                    return None;
+                }
+
+                let theta = r * degrees_to_radians(fov) / 2.;
+                let phi = y.atan2(x);
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    -theta.cos(),
+                );
+                Some(Ray::new(origin, direction))
+            }
+            Projection::Equirectangular => {
+                let longitude = (s - 0.5) * 2. * pi32;
+                let latitude = (0.5 - t) * pi32;
+                let direction = Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                );
+                Some(Ray::new(origin, direction))
+            }
+            Projection::Cylindrical { vfov } => {
+                let longitude = (s - 0.5) * 2. * pi32;
+                let y = (0.5 - t) * 2. * (degrees_to_radians(vfov) / 2.).tan();
+                let direction = Vec3::new(longitude.sin(), y, -longitude.cos());
+                Some(Ray::new(origin, direction))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).len() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn image_center_looks_forward() {
+        let forward = Vec3::new(0., 0., -1.);
+        let projections = [
+            Projection::Orthographic { view_height: 2. },
+            Projection::Fisheye { fov: 180. },
+            Projection::Equirectangular,
+            Projection::Cylindrical { vfov: 90. },
+        ];
+
+        for projection in projections {
+            let ray = projection.camera_space_ray(0.5, 0.5, 2.).unwrap();
+            assert_near(ray.direction().unit_vec(), forward);
+        }
+    }
+
+    #[test]
+    fn panoramas_wrap_around() {
+        let behind = Vec3::new(0., 0., 1.);
+        let left = Projection::Equirectangular.camera_space_ray(0., 0.5, 2.).unwrap();
+        let right = Projection::Cylindrical { vfov: 90. }.camera_space_ray(1., 0.5, 2.).unwrap();
+
+        assert_near(*left.direction(), behind);
+        assert_near(*right.direction(), behind);
+        assert!(Projection::Fisheye { fov: 180. }.camera_space_ray(0., 0., 1.).is_none());
+    }
+}
@@ -1,11 +1,15 @@
 use std::rc::Rc;
 
+use fastrand::Rng;
+
 use crate::{
     materials::material::Material,
+    render::stats,
     utility::{
         interval::Interval,
         ray::Ray,
-        vec3::{Point3, Precision},
+        utils::pi32,
+        vec3::{Point3, Precision, Vec3},
     },
 };
 
@@ -25,10 +29,42 @@ impl Sphere {
             mat,
         }
     }
+
+    pub fn center(&self) -> Point3 {
+        self.center
+    }
+
+    pub fn radius(&self) -> Precision {
+        self.radius
+    }
+
+    pub fn material(&self) -> &Rc<dyn Material> {
+        &self.mat
+    }
+
+    pub fn area(&self) -> Precision {
+        4. * pi32 * self.radius * self.radius
+    }
+
+    /// Surface coordinates of the point with outward normal `n`: `u` goes around the y
+    /// axis starting from -x, `v` from the bottom to the top.
+    fn uv(n: &Vec3) -> (Precision, Precision) {
+        let theta = (-n.y()).clamp(-1., 1.).acos();
+        let phi = (-n.z()).atan2(n.x()) + pi32;
+        (phi / (2. * pi32), theta / pi32)
+    }
+
+    /// Uniformly distributed point on the surface, with the outward normal there.
+    pub fn sample_surface(&self, rng: &mut Rng) -> (Point3, Vec3) {
+        let normal = Vec3::random_unit_vec(rng);
+        (self.center + self.radius * normal, normal)
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        stats::record(|counters| counters.primitive_tests += 1);
+
         let oc = self.center - *r.origin();
         let a = r.direction().len_square();
         let h = r.direction().dot(&oc);
@@ -54,6 +90,7 @@ impl Hittable for Sphere {
         rec.p = r.at(rec.t);
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
+        (rec.u, rec.v) = Sphere::uv(&outward_normal);
         rec.material = self.mat.clone();
 
         true
@@ -0,0 +1,93 @@
+use crate::utility::vec3::Precision;
+
+/// How the two eyes of a stereo rig are aimed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StereoMode {
+    /// Parallel optical axes, with the images shifted so that objects at the convergence
+    /// distance have zero parallax. Free of vertical parallax.
+    #[default]
+    Parallel,
+    /// Both eyes rotated to look at the convergence point.
+    ToeIn,
+}
+
+/// How the two eye images are packed into the output image.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StereoLayout {
+    /// Left eye on the left half, right eye on the right half.
+    #[default]
+    SideBySide,
+    /// Left eye on the top half, right eye on the bottom half.
+    OverUnder,
+}
+
+/// Stereo camera rig. With panoramic projections the rig renders omni-directional
+/// stereo, offsetting every ray sideways from its own viewing direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoSettings {
+    pub interocular_distance: Precision,
+    pub convergence_distance: Precision,
+    pub mode: StereoMode,
+    pub layout: StereoLayout,
+}
+
+impl Default for StereoSettings {
+    fn default() -> Self {
+        let interocular_distance = 0.064;
+        let convergence_distance = 10.;
+        let mode = StereoMode::default();
+        let layout = StereoLayout::default();
+
+        Self {
+            interocular_distance,
+            convergence_distance,
+            mode,
+            layout,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    /// Side of the rig center the eye is on, along the camera's right vector.
+    pub fn sign(&self) -> Precision {
+        match self {
+            Eye::Left => -1.,
+            Eye::Right => 1.,
+        }
+    }
+}
+
+/// Packs two row major `cols` x `rows` images into one, following `layout`.
+pub fn combine<T: Copy>(left: &[T], right: &[T], cols: usize, rows: usize, layout: StereoLayout) -> Vec<T> {
+    assert!(left.len() == cols * rows && right.len() == cols * rows);
+
+    match layout {
+        StereoLayout::SideBySide => (0..rows)
+            .flat_map(|row| {
+                let range = row * cols..(row + 1) * cols;
+                left[range.clone()].iter().chain(&right[range]).copied()
+            })
+            .collect(),
+        StereoLayout::OverUnder => left.iter().chain(right).copied().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts() {
+        let left = [1, 2, 3, 4];
+        let right = [5, 6, 7, 8];
+
+        assert_eq!(combine(&left, &right, 2, 2, StereoLayout::SideBySide), [1, 2, 5, 6, 3, 4, 7, 8]);
+        assert_eq!(combine(&left, &right, 2, 2, StereoLayout::OverUnder), [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+}
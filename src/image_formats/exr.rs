@@ -0,0 +1,148 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// One channel of an EXR image, such as `R` or `depth.Z`. A dot in the name separates
+/// the layer from the channel.
+#[derive(Debug, PartialEq, PartialOrd, Clone)]
+pub struct ExrChannel {
+    pub name: String,
+    /// One value per pixel, in row major order.
+    pub values: Vec<f32>,
+}
+
+/// Uncompressed, single part, scanline OpenEXR image with 32 bit float channels.
+#[derive(Debug, PartialEq, PartialOrd, Clone)]
+pub struct EXR {
+    cols: usize,
+    rows: usize,
+    channels: Vec<ExrChannel>,
+}
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const FLOAT: i32 = 2;
+
+impl EXR {
+    pub fn new(cols: usize, rows: usize, mut channels: Vec<ExrChannel>) -> Self {
+        assert!(channels.iter().all(|channel| channel.values.len() == cols * rows));
+
+        // Readers expect the channels sorted by name.
+        channels.sort_by(|a, b| a.name.cmp(&b.name));
+        Self { cols, rows, channels }
+    }
+
+    pub fn channels(&self) -> &[ExrChannel] {
+        &self.channels
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let header = self.header();
+        out.write_all(&header)?;
+
+        // Offset table, one uncompressed scanline per block.
+        let line_size = 8 + self.cols * self.channels.len() * 4;
+        let first_line = header.len() + 8 * self.rows;
+        for row in 0..self.rows {
+            out.write_all(&((first_line + row * line_size) as u64).to_le_bytes())?;
+        }
+
+        for row in 0..self.rows {
+            out.write_all(&(row as i32).to_le_bytes())?;
+            out.write_all(&((line_size - 8) as i32).to_le_bytes())?;
+            for channel in &self.channels {
+                for value in &channel.values[row * self.cols..(row + 1) * self.cols] {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        // Version 2, single part scanline image. Channel names longer than 31 bytes
+        // need the long names flag.
+        let long_names = self.channels.iter().any(|channel| channel.name.len() > 31);
+        header.extend_from_slice(&[2, if long_names { 0x04 } else { 0 }, 0, 0]);
+
+        let mut channels = Vec::new();
+        for channel in &self.channels {
+            channels.extend_from_slice(channel.name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&FLOAT.to_le_bytes());
+            // pLinear and reserved bytes, then x and y sampling.
+            channels.extend_from_slice(&[0; 4]);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+
+        let window: Vec<u8> = [0, 0, self.cols as i32 - 1, self.rows as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+
+        attribute(&mut header, "channels", "chlist", &channels);
+        attribute(&mut header, "compression", "compression", &[0]);
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+
+        header
+    }
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scanlines_follow_the_offset_table() {
+        let channel = |name: &str, values: Vec<f32>| ExrChannel {
+            name: name.to_string(),
+            values,
+        };
+        let exr = EXR::new(2, 3, vec![channel("Z", vec![0.; 6]), channel("A", (0..6).map(|v| v as f32).collect())]);
+        assert_eq!(exr.channels()[0].name, "A");
+
+        let mut bytes = Vec::new();
+        exr.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes[..4], MAGIC);
+
+        let header_len = exr.header().len();
+        let offset = |row: usize| {
+            let start = header_len + 8 * row;
+            u64::from_le_bytes(bytes[start..start + 8].try_into().unwrap()) as usize
+        };
+
+        // Last scanline: row 2, 16 bytes of data, then A = 4, 5 and Z = 0, 0.
+        let last = offset(2);
+        assert_eq!(bytes.len(), last + 8 + 16);
+        assert_eq!(bytes[last..last + 4], 2i32.to_le_bytes());
+        assert_eq!(bytes[last + 4..last + 8], 16i32.to_le_bytes());
+        assert_eq!(bytes[last + 8..last + 12], 4f32.to_le_bytes());
+        assert_eq!(bytes[last + 12..last + 16], 5f32.to_le_bytes());
+    }
+}
@@ -1,4 +1,13 @@
-use crate::utility::{color::Color, vec3::Precision};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::utility::{
+    color::{srgb_to_linear, Color},
+    vec3::Precision,
+};
 
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
 pub struct PPM {
@@ -21,31 +30,149 @@ impl PPM {
         let mut values = Vec::with_capacity(cols * rows);
 
         for row in 0..rows {
-            eprintln!("Scanlines remaining: {}", rows - row);
             for col in 0..cols {
                 values.push(gen(row as Precision, col as Precision));
             }
         }
 
-        eprintln!("Done! :D");
-
         PPM::new(cols, rows, max_color, values)
     }
 
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Pixel values in row major order.
+    pub fn values(&self) -> &[Color] {
+        &self.values
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Color {
+        self.values[row * self.cols + col]
+    }
+
+    /// Applies `f` to every pixel.
+    pub fn map<F>(&self, f: F) -> Self
+    where
+        F: Fn(Color) -> Color,
+    {
+        let values = self.values.iter().map(|&c| f(c)).collect();
+        PPM::new(self.cols, self.rows, self.max_color, values)
+    }
+
+    /// Writes the image to stdout.
     pub fn output(&self) {
-        println!("P3");
-        println!("{} {}", self.cols, self.rows);
-        println!("{}", self.max_color);
-        
+        let stdout = io::stdout();
+        self.write_to(&mut BufWriter::new(stdout.lock()))
+            .expect("failed to write image to stdout");
+    }
+
+    /// Writes the image to a file at `path`, replacing it if it exists.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    /// Reads a plain (P3) or raw (P6) PPM file, decoding sRGB values to linear colors.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        PPM::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_from(mut input: impl Read) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+
+        let mut header = HeaderReader { bytes: &bytes, pos: 0 };
+        let magic = header.token()?;
+        let cols = header.number()? as usize;
+        let rows = header.number()? as usize;
+        let max_value = header.number()?;
+        if max_value == 0 || max_value > 255 {
+            return Err(invalid_data("only 8 bit PPM images are supported"));
+        }
+
+        let samples: Vec<u32> = match magic.as_str() {
+            "P3" => (0..cols * rows * 3).map(|_| header.number()).collect::<io::Result<_>>()?,
+            "P6" => {
+                // A single whitespace character separates the header from the raster.
+                let start = header.pos + 1;
+                let raster = bytes
+                    .get(start..start + cols * rows * 3)
+                    .ok_or_else(|| invalid_data("truncated PPM raster"))?;
+                raster.iter().map(|&b| b as u32).collect()
+            }
+            _ => return Err(invalid_data("not a P3 or P6 PPM image")),
+        };
+
+        let decode = |v: u32| srgb_to_linear(v as Precision / max_value as Precision);
+        let values = samples
+            .chunks_exact(3)
+            .map(|rgb| Color::new(decode(rgb[0]), decode(rgb[1]), decode(rgb[2])))
+            .collect();
+
+        Ok(PPM::new(cols, rows, 255, values))
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.cols, self.rows)?;
+        writeln!(out, "{}", self.max_color)?;
+
         for row in 0..self.rows {
             for col in 0..self.cols {
-                let rgb = self.values[row * self.cols + col];
-                rgb.write_color();
+                self.get(row, col).write_color(out)?;
             }
         }
+
+        Ok(())
     }
 }
 
+/// Splits a PPM header into whitespace separated tokens, skipping comments.
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl HeaderReader<'_> {
+    fn token(&mut self) -> io::Result<String> {
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'#') => {
+                    while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => self.pos += 1,
+                Some(_) => break,
+                None => return Err(invalid_data("unexpected end of PPM data")),
+            }
+        }
+
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned())
+    }
+
+    fn number(&mut self) -> io::Result<u32> {
+        self.token()?
+            .parse()
+            .map_err(|_| invalid_data("invalid number in PPM data"))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,4 +199,22 @@ mod tests {
 
         ppm.output();
     }
+
+    #[test]
+    fn read_back_written_image() {
+        let values = vec![Color::new(0., 0.5, 1.), Color::new(0.25, 0.75, 0.125)];
+        let ppm = PPM::new(2, 1, 255, values);
+
+        let mut bytes = Vec::new();
+        ppm.write_to(&mut bytes).unwrap();
+        let read = PPM::read_from(&bytes[..]).unwrap();
+
+        assert_eq!((read.cols(), read.rows()), (2, 1));
+        for (a, b) in ppm.values().iter().zip(read.values()) {
+            assert!((*a - *b).len() < 0.01, "{a} != {b}");
+        }
+
+        let raw = b"P6\n# comment\n1 1\n255\n\xff\x00\xff";
+        assert_eq!(PPM::read_from(&raw[..]).unwrap().get(0, 0), Color::new(1., 0., 1.));
+    }
 }
@@ -0,0 +1,512 @@
+use std::{fmt, rc::Rc};
+
+use fastrand::Rng;
+
+use crate::{
+    figures::{
+        hittable::{HitRecord, Hittable},
+        sphere::Sphere,
+    },
+    materials::material::Lobe,
+    render::{
+        aov::SurfaceSample,
+        trace::{Bounce, Scattering},
+    },
+    utility::{
+        color::Color,
+        interval::Interval,
+        ray::Ray,
+        utils::pi32,
+        vec3::{Point3, Precision, Vec3},
+    },
+};
+
+use super::{
+    integrator::{background, Integrator, PathRecord, Sensor, Splat, Termination},
+    lights,
+};
+
+/// Bidirectional path tracer after Veach's thesis. Every camera path is joined with a
+/// path started on one of `lights` in all possible ways, weighted against each other
+/// with multiple importance sampling (balance heuristic).
+///
+/// Light paths only start on `lights`, the sky is only found by camera paths. Vertices on
+/// delta lobes, such as glass, are never connected to, and are only reached by following
+/// `sample`. Light paths are also joined to the camera itself when it is a pinhole, which is what resolves
+/// caustics seen directly; that light lands anywhere on the image and is left out of the
+/// render passes.
+#[derive(Clone)]
+pub struct BidirectionalPathTracer {
+    /// Maximum number of segments of the joined paths.
+    pub max_depth: i32,
+    /// Emitting spheres of the world to start light paths on.
+    pub lights: Vec<Rc<Sphere>>,
+}
+
+impl fmt::Debug for BidirectionalPathTracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BidirectionalPathTracer")
+            .field("max_depth", &self.max_depth)
+            .field("lights", &self.lights.len())
+            .finish()
+    }
+}
+
+/// A vertex of a camera or light path.
+#[derive(Clone)]
+struct Vertex {
+    p: Point3,
+    /// Normal on the side the vertex was reached from, outward for light vertices and
+    /// zero for the camera.
+    n: Vec3,
+    /// Hit surface and the ray that reached it.
+    surface: Option<(HitRecord, Ray)>,
+    /// Throughput of the path up to the vertex.
+    beta: Color,
+    /// Light given off by the vertex, back along the path.
+    emitted: Color,
+    /// Area densities of the vertex being sampled by its own path and by a path coming
+    /// from the other direction.
+    pdf_fwd: Precision,
+    pdf_rev: Precision,
+    /// Scattered through a discrete direction, so it cannot be joined to.
+    delta: bool,
+    lobe: Option<Lobe>,
+}
+
+impl Vertex {
+    fn new(p: Point3, n: Vec3, beta: Color) -> Self {
+        Self {
+            p,
+            n,
+            surface: None,
+            beta,
+            emitted: Color::default(),
+            pdf_fwd: 0.,
+            pdf_rev: 0.,
+            delta: false,
+            lobe: None,
+        }
+    }
+
+    /// Converts a density per solid angle of sampling `to` from here into a density per
+    /// unit area at `to`.
+    fn area_density(&self, pdf: Precision, to: &Vertex) -> Precision {
+        let d = to.p - self.p;
+        let distance_square = d.len_square();
+        if distance_square == 0. {
+            return 0.;
+        }
+        pdf * to.n.dot(&(d / distance_square.sqrt())).abs() / distance_square
+    }
+}
+
+/// Densities of a vertex of a joined path, for one of the ways of sampling the path.
+#[derive(Debug, Clone, Copy)]
+struct Densities {
+    from_camera: Precision,
+    from_light: Precision,
+    delta: bool,
+}
+
+fn remap(pdf: Precision) -> Precision {
+    if pdf == 0. {
+        1.
+    } else {
+        pdf
+    }
+}
+
+impl BidirectionalPathTracer {
+    /// Extends `path` by following the scattered rays, until it leaves the world, is
+    /// absorbed or holds `max_vertices` vertices. Returns the ray that left the world
+    /// and its throughput, if any, along with why the walk ended.
+    #[allow(clippy::too_many_arguments)]
+    fn walk(
+        &self,
+        world: &dyn Hittable,
+        mut ray: Ray,
+        mut beta: Color,
+        mut pdf_dir: Precision,
+        max_vertices: usize,
+        rng: &mut Rng,
+        path: &mut Vec<Vertex>,
+        record: &mut PathRecord,
+    ) -> (Option<(Ray, Color)>, Termination) {
+        loop {
+            if path.len() >= max_vertices {
+                return (None, Termination::MaxDepth);
+            }
+
+            record.rays += 1;
+            let mut rec = HitRecord::default();
+            if !world.hit(&ray, Interval::new(0.001, Precision::INFINITY), &mut rec) {
+                return (Some((ray, beta)), Termination::Escaped);
+            }
+
+            let prev = path.len() - 1;
+            let mut vertex = Vertex::new(rec.p, rec.normal, beta);
+            vertex.pdf_fwd = path[prev].area_density(pdf_dir, &vertex);
+            vertex.emitted = rec.material.emitted(&rec);
+
+            let sample = rec.material.sample(&ray, &rec, rng);
+            vertex.surface = Some((rec.clone(), ray.clone()));
+            path.push(vertex);
+
+            if let Some(trace) = &mut record.trace {
+                let mut bounce = Bounce::new(&rec);
+                if let Some(sample) = &sample {
+                    bounce.scattered = Some(Scattering::from(sample));
+                    bounce.throughput = beta * sample.weight;
+                }
+                trace.push(bounce);
+            }
+
+            let Some(sample) = sample else {
+                return (None, Termination::Absorbed);
+            };
+
+            let current = prev + 1;
+            let direction = *sample.ray.direction();
+            let pdf_rev = if sample.delta {
+                path[current].delta = true;
+                0.
+            } else {
+                let reversed = Ray::new(rec.p + direction, -direction);
+                rec.material.pdf(&reversed, &rec, &-*ray.direction())
+            };
+            path[current].lobe = Some(sample.lobe);
+            path[prev].pdf_rev = path[current].area_density(pdf_rev, &path[prev]);
+
+            beta *= sample.weight;
+            ray = sample.ray;
+            pdf_dir = if sample.delta { 0. } else { sample.pdf };
+        }
+    }
+
+    /// Starts a path on a random light and follows it. Only camera paths are traced into
+    /// `record.trace`.
+    fn light_path(&self, world: &dyn Hittable, rng: &mut Rng, record: &mut PathRecord) -> Vec<Vertex> {
+        let mut path = Vec::new();
+        if self.max_depth < 1 {
+            return path;
+        }
+        let Some(emission) = lights::sample_emission(&self.lights, rng) else {
+            return path;
+        };
+
+        let (p, n) = (emission.rec.p, emission.rec.normal);
+        let mut vertex = Vertex::new(p, n, emission.emitted / emission.pdf_pos);
+        vertex.emitted = emission.emitted;
+        vertex.pdf_fwd = emission.pdf_pos;
+        vertex.surface = Some((emission.rec, Ray::new(p, -n)));
+        path.push(vertex);
+
+        let ray = Ray::new(p, emission.direction);
+        let beta = emission.emitted * pi32 / emission.pdf_pos;
+        let trace = record.trace.take();
+        self.walk(world, ray, beta, emission.pdf_dir, self.max_depth as usize, rng, &mut path, record);
+        record.trace = trace;
+        path
+    }
+
+    /// Light carried to the camera by the first `s` vertices of `light`, before weighting.
+    fn connect_to_camera(
+        &self,
+        world: &dyn Hittable,
+        light: &[Vertex],
+        s: usize,
+        sensor: &dyn Sensor,
+        record: &mut PathRecord,
+    ) -> Option<Splat> {
+        let z = &light[s - 1];
+        if z.delta {
+            return None;
+        }
+        let connection = sensor.connect(z.p)?;
+
+        let d = connection.origin - z.p;
+        let distance = d.len();
+        if distance <= 0.002 {
+            return None;
+        }
+        let direction = d / distance;
+
+        let f_z = if s == 1 {
+            Color::new(1., 1., 1.) * z.n.dot(&direction).max(0.)
+        } else {
+            let (rec_z, ray_z) = z.surface.as_ref().unwrap();
+            rec_z.material.eval(ray_z, rec_z, &direction)
+        };
+
+        let color = z.beta * f_z * connection.importance / (distance * distance);
+        if color.near_zero() {
+            return None;
+        }
+
+        record.rays += 1;
+        let mut rec = HitRecord::default();
+        let shadow = Ray::new(z.p, direction);
+        if world.hit(&shadow, Interval::new(0.001, distance - 0.001), &mut rec) {
+            return None;
+        }
+
+        Some(Splat {
+            x: connection.x,
+            y: connection.y,
+            color,
+        })
+    }
+
+    /// Light carried by the path joining the first `s` vertices of `light` and the first
+    /// `t` vertices of `camera`, before weighting.
+    fn connect(
+        &self,
+        world: &dyn Hittable,
+        camera: &[Vertex],
+        light: &[Vertex],
+        s: usize,
+        t: usize,
+        record: &mut PathRecord,
+    ) -> Color {
+        let black = Color::new(0., 0., 0.);
+        let y = &camera[t - 1];
+
+        if s == 0 {
+            return y.beta * y.emitted;
+        }
+
+        let z = &light[s - 1];
+        if y.delta || z.delta {
+            return black;
+        }
+        let Some((rec_y, ray_y)) = &y.surface else {
+            return black;
+        };
+
+        let d = z.p - y.p;
+        let distance = d.len();
+        if distance <= 0.002 {
+            return black;
+        }
+        let direction = d / distance;
+
+        let f_y = rec_y.material.eval(ray_y, rec_y, &direction);
+        let f_z = if s == 1 {
+            // The emitted light is already in the throughput.
+            Color::new(1., 1., 1.) * z.n.dot(&-direction).max(0.)
+        } else {
+            let (rec_z, ray_z) = z.surface.as_ref().unwrap();
+            rec_z.material.eval(ray_z, rec_z, &-direction)
+        };
+
+        let contribution = y.beta * f_y * f_z * z.beta / (distance * distance);
+        if contribution.near_zero() {
+            return black;
+        }
+
+        record.rays += 1;
+        let mut rec = HitRecord::default();
+        let shadow = Ray::new(y.p, direction);
+        if world.hit(&shadow, Interval::new(0.001, distance - 0.001), &mut rec) {
+            return black;
+        }
+
+        contribution
+    }
+
+    /// Balance heuristic weight of joining `s` light vertices and `t` camera vertices,
+    /// against all the other ways of sampling the same path. Light paths can only be
+    /// joined to the camera itself if there is a `sensor`.
+    fn mis_weight(
+        &self,
+        camera: &[Vertex],
+        light: &[Vertex],
+        s: usize,
+        t: usize,
+        sensor: Option<&dyn Sensor>,
+    ) -> Precision {
+        let mut path: Vec<Densities> = camera[..t]
+            .iter()
+            .map(|v| Densities {
+                from_camera: v.pdf_fwd,
+                from_light: v.pdf_rev,
+                delta: v.delta,
+            })
+            .chain(light[..s].iter().rev().map(|v| Densities {
+                from_camera: v.pdf_rev,
+                from_light: v.pdf_fwd,
+                delta: v.delta,
+            }))
+            .collect();
+        let k = path.len() - 1;
+
+        // Densities around the join, which the subpaths could not know.
+        let y = &camera[t - 1];
+        if s == 0 {
+            let (rec_y, _) = y.surface.as_ref().unwrap();
+            let Some(pdf_pos) = lights::light_density(&self.lights, rec_y) else {
+                // No other way to find this light.
+                return 1.;
+            };
+            path[t - 1].from_light = pdf_pos;
+            path[t - 1].delta = false;
+            if t >= 3 {
+                let prev = &camera[t - 2];
+                path[t - 2].from_light = y.area_density(lights::cosine_density(&y.n, &(prev.p - y.p)), prev);
+            }
+        } else {
+            let z = &light[s - 1];
+            let to_z = z.p - y.p;
+
+            if t == 1 {
+                let pdf = sensor.map_or(0., |sensor| sensor.pdf(&to_z));
+                path[1].from_camera = y.area_density(pdf, z);
+            } else {
+                let (rec_y, ray_y) = y.surface.as_ref().unwrap();
+                let pdf = if s == 1 {
+                    lights::cosine_density(&z.n, &-to_z)
+                } else {
+                    let (rec_z, ray_z) = z.surface.as_ref().unwrap();
+                    rec_z.material.pdf(ray_z, rec_z, &-to_z)
+                };
+                path[t - 1].from_light = z.area_density(pdf, y);
+
+                if t >= 3 {
+                    let prev = &camera[t - 2];
+                    let pdf = rec_y.material.pdf(&Ray::new(z.p, to_z), rec_y, &(prev.p - y.p));
+                    path[t - 2].from_light = y.area_density(pdf, prev);
+                }
+
+                path[t].from_camera = y.area_density(rec_y.material.pdf(ray_y, rec_y, &to_z), z);
+            }
+
+            if s >= 2 {
+                let (rec_z, _) = z.surface.as_ref().unwrap();
+                let next = &light[s - 2];
+                let pdf = rec_z.material.pdf(&Ray::new(y.p, to_z), rec_z, &(next.p - z.p));
+                path[t + 1].from_camera = z.area_density(pdf, next);
+            }
+        }
+
+        let mut sum = 0.;
+
+        // Fewer camera vertices, down to joining at the camera itself if possible.
+        let min_t = if sensor.is_some() { 1 } else { 2 };
+        let mut ratio = 1.;
+        for i in (min_t..t).rev() {
+            ratio *= remap(path[i].from_light) / remap(path[i].from_camera);
+            if !path[i].delta && !path[i - 1].delta {
+                sum += ratio;
+            }
+        }
+
+        // More camera vertices, up to the camera path finding the light by itself.
+        let mut ratio = 1.;
+        for i in t..=k {
+            ratio *= remap(path[i].from_camera) / remap(path[i].from_light);
+            if i == k || (!path[i].delta && !path[i + 1].delta) {
+                sum += ratio;
+            }
+        }
+
+        1. / (1. + sum)
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        sensor: Option<&dyn Sensor>,
+        rng: &mut Rng,
+        record: &mut PathRecord,
+    ) -> Color {
+        let mut camera = vec![Vertex::new(*ray.origin(), Vec3::default(), Color::new(1., 1., 1.))];
+        let max_vertices = self.max_depth.max(0) as usize + 1;
+        let pdf_dir = sensor.map_or(0., |sensor| sensor.pdf(ray.direction()));
+        let (escaped, termination) = self.walk(
+            world,
+            ray.clone(),
+            Color::new(1., 1., 1.),
+            pdf_dir,
+            max_vertices,
+            rng,
+            &mut camera,
+            record,
+        );
+        record.termination = termination;
+        record.bounces = camera.iter().filter(|v| v.lobe.is_some()).count() as i32;
+
+        let first_lobe = camera.get(1).map(|v| v.lobe.unwrap_or_default());
+        if let (Some(aov), Some(first)) = (&mut record.aov, camera.get(1)) {
+            let (rec, first_ray) = first.surface.as_ref().unwrap();
+            aov.surface = Some(SurfaceSample::new(first_ray, rec));
+        }
+
+        let mut color = Color::new(0., 0., 0.);
+        if let Some((escaped, beta)) = escaped {
+            let bounces = camera.len() as i32 - 1;
+            let light = beta * background(&escaped);
+            if let Some(aov) = &mut record.aov {
+                aov.add_light(bounces, first_lobe.filter(|_| bounces > 0), light);
+            }
+            color += light;
+        }
+
+        let light = self.light_path(world, rng, record);
+        if let Some(sensor) = sensor {
+            for s in 1..=light.len().min(self.max_depth.max(0) as usize) {
+                if let Some(mut splat) = self.connect_to_camera(world, &light, s, sensor, record) {
+                    splat.color *= self.mis_weight(&camera, &light, s, 1, Some(sensor));
+                    record.splats.push(splat);
+                }
+            }
+        }
+
+        for t in 2..=camera.len() {
+            for s in 0..=light.len() {
+                let segments = s + t - 1;
+                if segments > self.max_depth as usize {
+                    break;
+                }
+
+                let contribution = self.connect(world, &camera, &light, s, t, record);
+                if contribution.near_zero() {
+                    continue;
+                }
+
+                let weighted = self.mis_weight(&camera, &light, s, t, sensor) * contribution;
+                if let Some(aov) = &mut record.aov {
+                    let bounce = segments as i32 - 1;
+                    aov.add_light(bounce, first_lobe.filter(|_| bounce > 0), weighted);
+                }
+                color += weighted;
+            }
+        }
+
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        integrators::integrator::tests::{assert_agrees_with_path_tracing, lamp_scene},
+        materials::metal::Metal,
+    };
+
+    use super::*;
+
+    #[test]
+    fn agrees_with_path_tracing() {
+        let (lamp, world) = lamp_scene(Rc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3)));
+        let bidirectional = BidirectionalPathTracer {
+            max_depth: 5,
+            lights: vec![lamp],
+        };
+
+        assert_agrees_with_path_tracing(&bidirectional, &world, 1, 40_000);
+    }
+}
@@ -0,0 +1,207 @@
+use fastrand::Rng;
+
+use crate::{
+    figures::hittable::{HitRecord, Hittable},
+    render::{
+        aov::{self, SurfaceSample},
+        stats,
+        trace::Bounce,
+    },
+    utility::{
+        color::Color,
+        interval::Interval,
+        ray::Ray,
+        vec3::{Precision, Vec3},
+    },
+};
+
+use super::integrator::{Integrator, PathRecord, Sensor, Termination};
+
+/// What the debug integrator shows of the surface the camera ray hits. Rays that hit
+/// nothing are black, except for `DebugMode::IntersectionCost`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugMode {
+    /// Outward normal, each component mapped from `[-1, 1]` to `[0, 1]`.
+    Normal,
+    /// Distance from the camera, black at the camera and white from `max_distance` on.
+    Depth { max_distance: Precision },
+    /// Surface coordinates, `u` in red and `v` in green.
+    Uv,
+    /// Front faces green, back faces red.
+    FrontFace,
+    /// Fraction of `samples` cosine distributed rays that get `distance` away from the
+    /// surface without hitting anything.
+    AmbientOcclusion { samples: u32, distance: Precision },
+    /// A color per material, hashed from its parameters, so that it does not depend on
+    /// the order materials are seen in.
+    MaterialId,
+    /// Ray-primitive intersection tests done for the camera ray, from blue for none
+    /// through green to red for `max_tests` and more.
+    IntersectionCost { max_tests: u64 },
+}
+
+/// Shades the image to inspect the geometry and the materials of the world, instead of
+/// its lighting.
+#[derive(Debug, Clone)]
+pub struct DebugIntegrator {
+    pub mode: DebugMode,
+}
+
+impl DebugIntegrator {
+    pub fn new(mode: DebugMode) -> Self {
+        Self { mode }
+    }
+
+    fn shade(&self, ray: &Ray, rec: &HitRecord, world: &dyn Hittable, rng: &mut Rng, path: &mut PathRecord) -> Color {
+        match self.mode {
+            DebugMode::Normal => {
+                let outward = if rec.front_face { rec.normal } else { -rec.normal };
+                0.5 * (outward + Vec3::new(1., 1., 1.))
+            }
+            DebugMode::Depth { max_distance } => {
+                let v = (rec.t * ray.direction().len() / max_distance).clamp(0., 1.);
+                Color::new(v, v, v)
+            }
+            DebugMode::Uv => Color::new(rec.u, rec.v, 0.),
+            DebugMode::FrontFace => {
+                if rec.front_face {
+                    Color::new(0., 1., 0.)
+                } else {
+                    Color::new(1., 0., 0.)
+                }
+            }
+            DebugMode::AmbientOcclusion { samples, distance } => {
+                let mut open = 0;
+                for _ in 0..samples {
+                    let mut direction = rec.normal + Vec3::random_unit_vec(rng);
+                    if direction.near_zero() {
+                        direction = rec.normal;
+                    }
+
+                    path.rays += 1;
+                    let occluder = Ray::new(rec.p, direction.unit_vec());
+                    if !world.hit(&occluder, Interval::new(0.001, distance), &mut HitRecord::default()) {
+                        open += 1;
+                    }
+                }
+
+                let v = open as Precision / samples.max(1) as Precision;
+                Color::new(v, v, v)
+            }
+            DebugMode::MaterialId => {
+                aov::id_color(aov::material_key(rec.material.as_ref()).max(1))
+            }
+            DebugMode::IntersectionCost { .. } => unreachable!("shaded without a hit"),
+        }
+    }
+}
+
+/// Heat map color of `t` between 0 and 1: blue, cyan, green, yellow, red.
+fn heat(t: Precision) -> Color {
+    let ramp = [
+        Color::new(0., 0., 1.),
+        Color::new(0., 1., 1.),
+        Color::new(0., 1., 0.),
+        Color::new(1., 1., 0.),
+        Color::new(1., 0., 0.),
+    ];
+
+    let x = t.clamp(0., 1.) * (ramp.len() - 1) as Precision;
+    let i = (x as usize).min(ramp.len() - 2);
+    let f = x - i as Precision;
+    (1. - f) * ramp[i] + f * ramp[i + 1]
+}
+
+impl Integrator for DebugIntegrator {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        _sensor: Option<&dyn Sensor>,
+        rng: &mut Rng,
+        path: &mut PathRecord,
+    ) -> Color {
+        // Intersection costs are counted even when the render keeps no statistics.
+        let counting = matches!(self.mode, DebugMode::IntersectionCost { .. }).then(|| stats::set_enabled(true));
+        let before = stats::snapshot();
+        path.rays += 1;
+
+        let mut rec = HitRecord::default();
+        let hit = world.hit(ray, Interval::new(0.001, Precision::INFINITY), &mut rec);
+        path.termination = if hit { Termination::Absorbed } else { Termination::Escaped };
+        if let Some(trace) = path.trace.as_mut().filter(|_| hit) {
+            trace.push(Bounce::new(&rec));
+        }
+
+        if let (DebugMode::IntersectionCost { max_tests }, Some(counting)) = (self.mode, counting) {
+            let tests = (stats::snapshot() - before).primitive_tests;
+            stats::set_enabled(counting);
+            return heat(tests as Precision / max_tests.max(1) as Precision);
+        }
+
+        if !hit {
+            return Color::new(0., 0., 0.);
+        }
+        if let Some(aov) = &mut path.aov {
+            aov.surface = Some(SurfaceSample::new(ray, &rec));
+        }
+
+        self.shade(ray, &rec, world, rng, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        figures::sphere::Sphere,
+        materials::lambertian::Lambertian,
+        utility::vec3::Point3,
+    };
+
+    use super::*;
+
+    #[test]
+    fn shows_the_geometry() {
+        let gray = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let world = vec![
+            Sphere::new(Point3::new(0., 0., -2.), 1., gray.clone()),
+            Sphere::new(Point3::new(0., -101., -2.), 100., gray),
+            Sphere::new(Point3::new(5., 0., -2.), 1., Rc::new(Lambertian::new(Color::new(0.1, 0.2, 0.3)))),
+        ];
+        let mut rng = Rng::with_seed(1);
+        let mut render = |mode, ray: &Ray| {
+            DebugIntegrator::new(mode).radiance(ray, &world, None, &mut rng, &mut PathRecord::default())
+        };
+
+        let ahead = Ray::new(Point3::new(0., 0., 0.), Vec3::new(0., 0., -1.));
+        let inside = Ray::new(Point3::new(0., 0., -2.), Vec3::new(0., 0., -1.));
+        let top = Ray::new(Point3::new(0., 5., -2.), Vec3::new(0., -1., 0.));
+
+        assert_eq!(render(DebugMode::Normal, &ahead), Color::new(0.5, 0.5, 1.));
+        assert_eq!(render(DebugMode::Normal, &inside), Color::new(0.5, 0.5, 0.));
+        assert_eq!(render(DebugMode::FrontFace, &ahead), Color::new(0., 1., 0.));
+        assert_eq!(render(DebugMode::FrontFace, &inside), Color::new(1., 0., 0.));
+        assert_eq!(render(DebugMode::Depth { max_distance: 4. }, &ahead), Color::new(0.25, 0.25, 0.25));
+        assert_eq!(render(DebugMode::Uv, &top), Color::new(0.5, 1., 0.));
+
+        // The top of the floor is open, where it meets the sphere it is not.
+        let ao = DebugMode::AmbientOcclusion { samples: 64, distance: 10. };
+        let open = render(ao, &Ray::new(Point3::new(-4., 0., -2.), Vec3::new(0., -1., 0.)));
+        let corner = render(ao, &Ray::new(Point3::new(0.5, -0.95, -2.), Vec3::new(0., -1., 0.)));
+        assert!(open.x() > 0.8 && corner.x() < 0.5, "{open} {corner}");
+
+        // Three spheres tested, a third of the way up the ramp.
+        let cost = render(DebugMode::IntersectionCost { max_tests: 9 }, &ahead);
+        assert!((cost - Color::new(0., 1., 2. / 3.)).len() < 1e-6, "{cost}");
+
+        // Colors do not depend on which material is seen first.
+        let blue = Ray::new(Point3::new(5., 0., 0.), Vec3::new(0., 0., -1.));
+        let other = render(DebugMode::MaterialId, &blue);
+        let first = render(DebugMode::MaterialId, &ahead);
+        assert_eq!(render(DebugMode::MaterialId, &Ray::new(Point3::new(0., 0., 0.), Vec3::new(0., -1., 0.))), first);
+        assert_ne!(other, first);
+        assert_eq!(render(DebugMode::MaterialId, &blue), other);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt::Debug;
+
+use fastrand::Rng;
+
+use crate::{
+    figures::{camera::lerp, hittable::Hittable},
+    render::{aov::AovSample, trace::Bounce},
+    utility::{
+        color::Color,
+        ray::Ray,
+        vec3::{Point3, Precision, Vec3},
+    },
+};
+
+/// Why a path stopped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Termination {
+    /// Left the world.
+    #[default]
+    Escaped,
+    /// Hit a surface that scattered nothing.
+    Absorbed,
+    /// Reached the bounce limit.
+    MaxDepth,
+    RussianRoulette,
+}
+
+/// What a single camera path did, filled in by the integrator tracing it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PathRecord {
+    /// Rays traced for the path, the camera ray included.
+    pub rays: u64,
+    /// Surfaces the path scattered off.
+    pub bounces: i32,
+    pub termination: Termination,
+    /// Render passes of the sample, `None` when not rendering any.
+    pub aov: Option<AovSample>,
+    /// Light carried straight to the camera by light paths, wherever it lands on the
+    /// image. It is not part of the returned radiance nor of the render passes.
+    pub splats: Vec<Splat>,
+    /// Surfaces the path hit, when tracing a single pixel, see `Camera::trace_pixel`.
+    pub trace: Option<Vec<Bounce>>,
+}
+
+/// Light reaching the camera at raster position `x`, `y`, see `Film::add_light`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Splat {
+    pub x: Precision,
+    pub y: Precision,
+    pub color: Color,
+}
+
+/// How the camera sees a point, see `Sensor::connect`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorConnection {
+    /// Point on the lens the camera sees from.
+    pub origin: Point3,
+    /// Raster position the point shows up at.
+    pub x: Precision,
+    pub y: Precision,
+    /// Importance of the direction towards the point, per solid angle, normalized so
+    /// that it integrates to 1 over a pixel.
+    pub importance: Precision,
+}
+
+/// A camera that light paths can be joined to.
+pub trait Sensor {
+    /// How the camera sees `p`, `None` if it is out of view.
+    fn connect(&self, p: Point3) -> Option<SensorConnection>;
+
+    /// Density per solid angle of the camera shooting a ray in `direction`, for a
+    /// sample anywhere on the image.
+    fn pdf(&self, direction: &Vec3) -> Precision;
+}
+
+/// Computes the light reaching the camera along camera rays.
+pub trait Integrator: Debug {
+    /// Light arriving along `ray` from `world`, recording what the path did in `path`.
+    /// `sensor` is the camera shooting `ray`, if light paths can be joined to it.
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &dyn Hittable,
+        sensor: Option<&dyn Sensor>,
+        rng: &mut Rng,
+        path: &mut PathRecord,
+    ) -> Color;
+
+    /// Called before every pass of samples over the image, for integrators that prepare
+    /// something from `world` first. Passes are numbered from 0, across resumed renders.
+    fn start_pass(&self, _world: &dyn Hittable, _pass: u64) {}
+}
+
+/// Scales `light` down so that no channel is above `limit`, keeping its hue.
+pub fn clamp_radiance(light: Color, limit: Precision) -> Color {
+    let max = light.x().max(light.y()).max(light.z());
+    if max > limit {
+        light * (limit / max)
+    } else {
+        light
+    }
+}
+
+/// Sky seen by rays that escape the world.
+pub fn background(r: &Ray) -> Color {
+    let unit_direction = r.direction().unit_vec();
+    let a = 0.5 * (unit_direction.y() + 1.0);
+    lerp(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0), a)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        figures::sphere::Sphere,
+        integrators::path::PathTracer,
+        materials::{diffuse_light::DiffuseLight, lambertian::Lambertian, material::Material},
+    };
+
+    use super::*;
+
+    /// A lamp over a gray floor, next to a sphere of `material`. Returns the lamp and the
+    /// world with all three.
+    pub(crate) fn lamp_scene(material: Rc<dyn Material>) -> (Rc<Sphere>, Vec<Rc<Sphere>>) {
+        let lamp = Rc::new(Sphere::new(
+            Point3::new(0., 2., 0.),
+            0.25,
+            Rc::new(DiffuseLight::new(Color::new(20., 16., 12.))),
+        ));
+        let world = vec![
+            lamp.clone(),
+            Rc::new(Sphere::new(Point3::new(0., -100., 0.), 100., Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+            Rc::new(Sphere::new(Point3::new(1., 0.5, 0.), 0.5, material)),
+        ];
+        (lamp, world)
+    }
+
+    /// Checks that `integrator` finds the same light as path tracing in a `lamp_scene`,
+    /// over `passes` passes of `trials` rays.
+    pub(crate) fn assert_agrees_with_path_tracing(
+        integrator: &dyn Integrator,
+        world: &dyn Hittable,
+        passes: u64,
+        trials: u32,
+    ) {
+        let path_tracer = PathTracer {
+            max_depth: 5,
+            russian_roulette: None,
+            clamp_indirect: None,
+        };
+
+        // Average light over a cone of rays looking down at the floor by the spheres.
+        let mean = |integrator: &dyn Integrator, seed| {
+            let mut rng = Rng::with_seed(seed);
+            let mut sum = Color::default();
+            for pass in 0..passes {
+                integrator.start_pass(world, pass);
+                for _ in 0..trials {
+                    let direction = Vec3::new(0.5, -1., 0.) + 0.3 * Vec3::random_in_unit_disk(&mut rng);
+                    let ray = Ray::new(Point3::new(0., 1.5, 3.), direction - Vec3::new(0., 0., 1.));
+                    sum += integrator.radiance(&ray, world, None, &mut rng, &mut PathRecord::default());
+                }
+            }
+            sum / (passes * trials as u64) as Precision
+        };
+
+        let expected = mean(&path_tracer, 1);
+        let actual = mean(integrator, 2);
+        assert!((actual - expected).len() < 0.05 * expected.len(), "{actual} != {expected}");
+    }
+}
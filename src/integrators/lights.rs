@@ -0,0 +1,81 @@
+use std::rc::Rc;
+
+use fastrand::Rng;
+
+use crate::{
+    figures::{hittable::HitRecord, sphere::Sphere},
+    utility::{
+        color::Color,
+        utils::pi32,
+        vec3::{Point3, Precision, Vec3},
+    },
+};
+
+/// Light leaving a random point of a light in a random direction, see `sample_emission`.
+#[derive(Clone)]
+pub struct Emission {
+    /// The point on the light, with the outward normal and the light material.
+    pub rec: HitRecord,
+    pub emitted: Color,
+    /// Direction the light leaves in, cosine distributed around the normal.
+    pub direction: Vec3,
+    /// Density per unit area of picking the point, among all the lights.
+    pub pdf_pos: Precision,
+    /// Density per solid angle of the direction.
+    pub pdf_dir: Precision,
+}
+
+/// Picks a light uniformly, a point uniformly on its surface and a diffuse emission
+/// direction there. `None` if there are no lights or the point gives off no light.
+pub fn sample_emission(lights: &[Rc<Sphere>], rng: &mut Rng) -> Option<Emission> {
+    if lights.is_empty() {
+        return None;
+    }
+
+    let light = &lights[rng.usize(..lights.len())];
+    let (p, normal) = light.sample_surface(rng);
+    let rec = HitRecord {
+        p,
+        normal,
+        material: light.material().clone(),
+        front_face: true,
+        ..Default::default()
+    };
+    let emitted = rec.material.emitted(&rec);
+    if emitted.near_zero() {
+        return None;
+    }
+
+    // Diffuse emission, sampled like a diffuse reflection.
+    let mut direction = normal + Vec3::random_unit_vec(rng);
+    if direction.near_zero() {
+        direction = normal;
+    }
+
+    Some(Emission {
+        pdf_pos: 1. / (lights.len() as Precision * light.area()),
+        pdf_dir: cosine_density(&normal, &direction),
+        rec,
+        emitted,
+        direction,
+    })
+}
+
+/// Area density of `sample_emission` picking the hit point, `None` if it is not on one
+/// of the lights.
+pub fn light_density(lights: &[Rc<Sphere>], rec: &HitRecord) -> Option<Precision> {
+    let material = Rc::as_ptr(&rec.material) as *const ();
+    lights
+        .iter()
+        .find(|light| Rc::as_ptr(light.material()) as *const () == material && on_surface(light, rec.p))
+        .map(|light| 1. / (lights.len() as Precision * light.area()))
+}
+
+fn on_surface(light: &Sphere, p: Point3) -> bool {
+    ((p - light.center()).len() - light.radius()).abs() <= 1e-3 * light.radius().max(1.)
+}
+
+/// Density per solid angle of a cosine distributed `direction` around `normal`.
+pub fn cosine_density(normal: &Vec3, direction: &Vec3) -> Precision {
+    normal.dot(&direction.unit_vec()).max(0.) / pi32
+}
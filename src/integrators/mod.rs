@@ -0,0 +1,7 @@
+pub mod bidirectional;
+pub mod debug;
+pub mod integrator;
+pub mod lights;
+pub mod path;
+pub mod photon;
+pub mod photon_map;
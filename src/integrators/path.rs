@@ -0,0 +1,197 @@
+use fastrand::Rng;
+
+use crate::{
+    figures::hittable::{HitRecord, Hittable},
+    render::{
+        aov::SurfaceSample,
+        roulette::RussianRoulette,
+        trace::{Bounce, Scattering},
+    },
+    utility::{color::Color, interval::Interval, ray::Ray, vec3::Precision},
+};
+
+use super::integrator::{background, clamp_radiance, Integrator, PathRecord, Sensor, Termination};
+
+/// Unidirectional path tracer following the rays scattered by the materials.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathTracer {
+    /// Maximum number of path segments.
+    pub max_depth: i32,
+    pub russian_roulette: Option<RussianRoulette>,
+    /// Largest channel value of the light found past the first surface, see
+    /// `ImageSettings::clamp_indirect`.
+    pub clamp_indirect: Option<Precision>,
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        let max_depth = 50;
+        let russian_roulette = None;
+        let clamp_indirect = None;
+
+        Self {
+            max_depth,
+            russian_roulette,
+            clamp_indirect,
+        }
+    }
+}
+
+impl PathTracer {
+    /// Scales down `light` found at segment `bounce` of the path to within
+    /// `clamp_indirect`, past the first surface.
+    fn clamp(&self, bounce: i32, light: Color) -> Color {
+        match self.clamp_indirect {
+            Some(limit) if bounce > 0 => clamp_radiance(light, limit),
+            _ => light,
+        }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        _sensor: Option<&dyn Sensor>,
+        rng: &mut Rng,
+        path: &mut PathRecord,
+    ) -> Color {
+        let mut ray = r.clone();
+        let mut throughput = Color::new(1., 1., 1.);
+        let mut color = Color::new(0., 0., 0.);
+        let mut first_lobe = None;
+
+        for bounce in 0..self.max_depth {
+            path.rays += 1;
+
+            let mut rec = HitRecord::default();
+            if !world.hit(&ray, Interval::new(0.001, Precision::INFINITY), &mut rec) {
+                let light = self.clamp(bounce, throughput * background(&ray));
+                if let Some(aov) = &mut path.aov {
+                    aov.add_light(bounce, first_lobe, light);
+                }
+                path.termination = Termination::Escaped;
+                return color + light;
+            }
+
+            let light = self.clamp(bounce, throughput * rec.material.emitted(&rec));
+            color += light;
+            if let Some(aov) = &mut path.aov {
+                aov.add_light(bounce, first_lobe, light);
+                if bounce == 0 {
+                    aov.surface = Some(SurfaceSample::new(&ray, &rec));
+                }
+            }
+            if let Some(trace) = &mut path.trace {
+                trace.push(Bounce::new(&rec));
+            }
+
+            let Some(scattered_ray) = rec.material.scatter(&ray, &rec, rng) else {
+                path.termination = Termination::Absorbed;
+                return color;
+            };
+            // Only traces need the density of the direction.
+            if let Some(bounce) = path.trace.as_mut().and_then(|trace| trace.last_mut()) {
+                bounce.scattered = Some(Scattering {
+                    direction: *scattered_ray.ray.direction(),
+                    attenuation: scattered_ray.attenuation,
+                    lobe: scattered_ray.lobe,
+                    pdf: rec.material.pdf(&ray, &rec, scattered_ray.ray.direction()),
+                });
+            }
+            path.bounces += 1;
+            first_lobe.get_or_insert(scattered_ray.lobe);
+            throughput *= scattered_ray.attenuation;
+            ray = scattered_ray.ray;
+
+            if let Some(roulette) = &self.russian_roulette {
+                match roulette.play(bounce + 1, throughput, rng) {
+                    Some(boosted) => throughput = boosted,
+                    None => {
+                        path.termination = Termination::RussianRoulette;
+                        return color;
+                    }
+                }
+            }
+            if let Some(bounce) = path.trace.as_mut().and_then(|trace| trace.last_mut()) {
+                bounce.throughput = throughput;
+            }
+        }
+
+        path.termination = Termination::MaxDepth;
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        figures::sphere::Sphere,
+        materials::{dielectric::Dielectric, diffuse_light::DiffuseLight, lambertian::Lambertian},
+        utility::vec3::{Point3, Vec3},
+    };
+
+    use super::*;
+
+    #[test]
+    fn records_how_paths_end() {
+        let tracer = PathTracer {
+            max_depth: 500,
+            russian_roulette: None,
+            clamp_indirect: None,
+        };
+        let mut rng = Rng::with_seed(3);
+
+        let mut path = PathRecord::default();
+        let up = Ray::new(Point3::default(), Vec3::new(0., 1., 0.));
+        let sky = tracer.radiance(&up, &Vec::<Sphere>::new(), None, &mut rng, &mut path);
+        assert_eq!(sky, Color::new(0.5, 0.7, 1.));
+        assert_eq!((path.rays, path.bounces, path.termination), (1, 0, Termination::Escaped));
+
+        // Light trapped inside a glass sphere bounces until the limit, without
+        // recursing that deep.
+        let glass = vec![Sphere::new(Point3::default(), 1., Rc::new(Dielectric::new(1.5)))];
+        let trapped = Ray::new(Point3::new(0.9, 0., 0.), Vec3::new(0., 1., 0.));
+        let mut path = PathRecord::default();
+        tracer.radiance(&trapped, &glass, None, &mut rng, &mut path);
+        assert_eq!((path.rays, path.termination), (500, Termination::MaxDepth));
+
+        let ground = vec![Sphere::new(Point3::new(0., -100., 0.), 99., Rc::new(Lambertian::default()))];
+        let mut path = PathRecord::default();
+        tracer.radiance(&Ray::new(Point3::default(), Vec3::new(0., -1., 0.)), &ground, None, &mut rng, &mut path);
+        assert_eq!(path.termination, Termination::Escaped);
+        assert!(path.bounces >= 1);
+    }
+
+    #[test]
+    fn clamps_indirect_light() {
+        let world = vec![
+            Sphere::new(Point3::new(0., 2., 0.), 0.2, Rc::new(DiffuseLight::new(Color::new(50., 50., 50.)))),
+            Sphere::new(Point3::new(0., -100., 0.), 100., Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))),
+        ];
+        let brightest = |clamp_indirect, ray: &Ray| {
+            let tracer = PathTracer {
+                max_depth: 5,
+                russian_roulette: None,
+                clamp_indirect,
+            };
+            let mut rng = Rng::with_seed(4);
+            (0..2000)
+                .map(|_| tracer.radiance(ray, &world, None, &mut rng, &mut PathRecord::default()))
+                .map(|c| c.x().max(c.y()).max(c.z()))
+                .fold(0., Precision::max)
+        };
+
+        // Paths off the floor that find the small lamp are fireflies.
+        let floor = Ray::new(Point3::new(0., 1., 1.), Vec3::new(0., -1., -1.));
+        assert!(brightest(None, &floor) > 5.);
+        assert!(brightest(Some(1.), &floor) <= 1.);
+
+        // Light seen directly is left alone.
+        let lamp = Ray::new(Point3::new(0., 2., 1.), Vec3::new(0., 0., -1.));
+        assert_eq!(brightest(Some(1.), &lamp), 50.);
+    }
+}
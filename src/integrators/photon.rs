@@ -0,0 +1,295 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use fastrand::Rng;
+
+use crate::{
+    figures::{
+        hittable::{HitRecord, Hittable},
+        sphere::Sphere,
+    },
+    render::{
+        aov::SurfaceSample,
+        stats,
+        trace::{Bounce, Scattering},
+    },
+    utility::{
+        color::Color,
+        interval::Interval,
+        ray::Ray,
+        utils::{mix_seed, pi32},
+        vec3::Precision,
+    },
+};
+
+use super::{
+    integrator::{background, Integrator, PathRecord, Sensor, Termination},
+    lights,
+    photon_map::{Photon, PhotonMap},
+};
+
+/// Settings of the photon mapper.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhotonMapSettings {
+    /// Photons emitted from the lights before every pass.
+    pub photons: usize,
+    /// Radius photons are gathered within, in the first pass.
+    pub radius: Precision,
+    /// Shrinks the radius after every pass so that the render converges, keeping this
+    /// fraction of the photons of each new pass, between 0 and 1. `None` keeps the
+    /// radius, and the blur that comes with it.
+    pub progressive: Option<Precision>,
+    /// Maximum number of segments of photon and camera paths.
+    pub max_depth: i32,
+    pub seed: u64,
+}
+
+impl Default for PhotonMapSettings {
+    fn default() -> Self {
+        let photons = 100_000;
+        let radius = 0.1;
+        let progressive = Some(2. / 3.);
+        let max_depth = 50;
+        let seed = 0;
+
+        Self {
+            photons,
+            radius,
+            progressive,
+            max_depth,
+            seed,
+        }
+    }
+}
+
+/// Photon mapping integrator, after Jensen. Before every pass, photons are shot from
+/// `lights` and left on every surface they meet that is not a mirror. Camera paths follow
+/// mirrors and glass up to the first other surface, and estimate the light reflected
+/// there from the density of the photons around.
+///
+/// With `PhotonMapSettings::progressive`, every pass uses a new photon map with a smaller
+/// radius, after Knaus and Zwicker's probabilistic progressive photon mapping, so that
+/// averaging the passes converges. Render in passes to get there.
+///
+/// Only `lights` shoot photons. Light from the sky is found by carrying on the camera
+/// paths past the gather point, which ignore lights from then on.
+pub struct PhotonMapper {
+    settings: PhotonMapSettings,
+    lights: Vec<Rc<Sphere>>,
+    pass: RefCell<PassPhotons>,
+}
+
+/// Photons of the current pass.
+#[derive(Debug, Clone, Default)]
+struct PassPhotons {
+    map: PhotonMap,
+    radius: Precision,
+}
+
+impl fmt::Debug for PhotonMapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhotonMapper")
+            .field("settings", &self.settings)
+            .field("lights", &self.lights.len())
+            .finish()
+    }
+}
+
+impl PhotonMapper {
+    /// Photon mapper shooting photons from `lights`. Photons are shot when passes start,
+    /// see `Integrator::start_pass`.
+    pub fn new(lights: Vec<Rc<Sphere>>, settings: PhotonMapSettings) -> Self {
+        Self {
+            settings,
+            lights,
+            pass: RefCell::default(),
+        }
+    }
+
+    /// Gather radius of pass `pass`.
+    pub fn radius(&self, pass: u64) -> Precision {
+        let Some(alpha) = self.settings.progressive else {
+            return self.settings.radius;
+        };
+
+        let alpha = alpha.clamp(0., 1.);
+        let radius_square = (0..pass).fold(self.settings.radius * self.settings.radius, |r2, i| {
+            r2 * (i as Precision + alpha) / (i as Precision + 1.)
+        });
+        radius_square.sqrt()
+    }
+
+    /// Shoots the photons of a pass, returning them with the number of rays traced.
+    fn shoot(&self, world: &dyn Hittable, rng: &mut Rng) -> (Vec<Photon>, u64) {
+        let mut photons = Vec::new();
+        let mut rays = 0;
+
+        for _ in 0..self.settings.photons {
+            let Some(emission) = lights::sample_emission(&self.lights, rng) else {
+                continue;
+            };
+
+            let mut power = emission.emitted * pi32 / (emission.pdf_pos * self.settings.photons as Precision);
+            let mut ray = Ray::new(emission.rec.p, emission.direction);
+            for _ in 0..self.settings.max_depth {
+                rays += 1;
+                let mut rec = HitRecord::default();
+                if !world.hit(&ray, Interval::new(0.001, Precision::INFINITY), &mut rec) {
+                    break;
+                }
+
+                let Some(sample) = rec.material.sample(&ray, &rec, rng) else {
+                    break;
+                };
+                if !sample.delta {
+                    photons.push(Photon {
+                        p: rec.p,
+                        normal: rec.normal,
+                        direction: ray.direction().unit_vec(),
+                        power,
+                    });
+                }
+
+                power *= sample.weight;
+                ray = sample.ray;
+            }
+        }
+
+        (photons, rays)
+    }
+
+    /// Light reflected at `rec` towards where `ray` came from, estimated from the density
+    /// of the photons around.
+    fn gather(&self, ray: &Ray, rec: &HitRecord) -> Color {
+        let pass = self.pass.borrow();
+        let mut sum = Color::new(0., 0., 0.);
+        if pass.radius <= 0. {
+            return sum;
+        }
+
+        pass.map.for_each_within(rec.p, pass.radius, |photon| {
+            if photon.normal.dot(&rec.normal) <= 0. {
+                return;
+            }
+
+            let incoming = -photon.direction;
+            let cos = incoming.dot(&rec.normal);
+            if cos > 0. {
+                sum += photon.power * rec.material.eval(ray, rec, &incoming) / cos;
+            }
+        });
+
+        sum / (pi32 * pass.radius * pass.radius)
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn radiance(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        _sensor: Option<&dyn Sensor>,
+        rng: &mut Rng,
+        path: &mut PathRecord,
+    ) -> Color {
+        let mut ray = r.clone();
+        let mut throughput = Color::new(1., 1., 1.);
+        let mut color = Color::new(0., 0., 0.);
+        let mut first_lobe = None;
+        let mut gathered = false;
+
+        for bounce in 0..self.settings.max_depth {
+            path.rays += 1;
+
+            let mut rec = HitRecord::default();
+            if !world.hit(&ray, Interval::new(0.001, Precision::INFINITY), &mut rec) {
+                let light = throughput * background(&ray);
+                if let Some(aov) = &mut path.aov {
+                    aov.add_light(bounce, first_lobe, light);
+                }
+                path.termination = Termination::Escaped;
+                return color + light;
+            }
+
+            // Past the gather point, the photons hold the light of the lights.
+            if !gathered {
+                let light = throughput * rec.material.emitted(&rec);
+                color += light;
+                if let Some(aov) = &mut path.aov {
+                    aov.add_light(bounce, first_lobe, light);
+                }
+            }
+            if let (Some(aov), 0) = (&mut path.aov, bounce) {
+                aov.surface = Some(SurfaceSample::new(&ray, &rec));
+            }
+            if let Some(trace) = &mut path.trace {
+                trace.push(Bounce::new(&rec));
+            }
+
+            let Some(sample) = rec.material.sample(&ray, &rec, rng) else {
+                path.termination = Termination::Absorbed;
+                return color;
+            };
+            if let Some(bounce) = path.trace.as_mut().and_then(|trace| trace.last_mut()) {
+                bounce.scattered = Some(Scattering::from(&sample));
+            }
+            path.bounces += 1;
+            first_lobe.get_or_insert(sample.lobe);
+
+            if !gathered && !sample.delta {
+                gathered = true;
+                // Filed as light reflected off the gather point, direct or not.
+                let light = throughput * self.gather(&ray, &rec);
+                color += light;
+                if let Some(aov) = &mut path.aov {
+                    aov.add_light(bounce + 1, first_lobe, light);
+                }
+            }
+
+            throughput *= sample.weight;
+            ray = sample.ray;
+            if let Some(bounce) = path.trace.as_mut().and_then(|trace| trace.last_mut()) {
+                bounce.throughput = throughput;
+            }
+        }
+
+        path.termination = Termination::MaxDepth;
+        color
+    }
+
+    fn start_pass(&self, world: &dyn Hittable, pass: u64) {
+        let mut rng = Rng::with_seed(mix_seed(self.settings.seed, pass));
+        let (photons, rays) = self.shoot(world, &mut rng);
+        stats::record(|counters| counters.secondary_rays += rays);
+
+        *self.pass.borrow_mut() = PassPhotons {
+            map: PhotonMap::new(photons),
+            radius: self.radius(pass),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        integrators::integrator::tests::{assert_agrees_with_path_tracing, lamp_scene},
+        materials::lambertian::Lambertian,
+    };
+
+    use super::*;
+
+    #[test]
+    fn converges_to_path_tracing() {
+        let (lamp, world) = lamp_scene(Rc::new(Lambertian::new(Color::new(0.8, 0.3, 0.3))));
+        let settings = PhotonMapSettings {
+            photons: 20_000,
+            radius: 0.2,
+            max_depth: 5,
+            ..Default::default()
+        };
+        let photon_mapper = PhotonMapper::new(vec![lamp], settings);
+        assert!(photon_mapper.radius(10) < photon_mapper.radius(1) && photon_mapper.radius(1) < settings.radius);
+
+        // The photon map is rebuilt every pass, and its radius shrinks.
+        assert_agrees_with_path_tracing(&photon_mapper, &world, 4, 20_000);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::utility::{
+    color::Color,
+    vec3::{Point3, Precision, Vec3},
+};
+
+/// Light flux that arrived at a surface, left there by a photon.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Photon {
+    pub p: Point3,
+    /// Normal on the side the photon arrived from.
+    pub normal: Vec3,
+    /// Unit direction the photon was travelling in.
+    pub direction: Vec3,
+    pub power: Color,
+}
+
+/// Photons stored in a balanced kd-tree, for finding the photons around a point.
+///
+/// The tree is implicit: the median of every slice along its split axis sits in the
+/// middle, with the two halves on either side.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    /// Split axis of the node at the same index.
+    axes: Vec<u8>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        build(&mut photons, &mut axes);
+        Self { photons, axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Calls `f` with every photon within `radius` of `p`.
+    pub fn for_each_within(&self, p: Point3, radius: Precision, mut f: impl FnMut(&Photon)) {
+        query(&self.photons, &self.axes, p, radius * radius, &mut f);
+    }
+}
+
+fn build(photons: &mut [Photon], axes: &mut [u8]) {
+    if photons.len() <= 1 {
+        return;
+    }
+
+    // Split along the longest side of the bounds.
+    let (mut min, mut max) = (photons[0].p, photons[0].p);
+    for photon in photons.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(photon.p[axis]);
+            max[axis] = max[axis].max(photon.p[axis]);
+        }
+    }
+    let extent = max - min;
+    let axis = (0..3).max_by(|&a, &b| extent[a].total_cmp(&extent[b])).unwrap();
+
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+    axes[mid] = axis as u8;
+
+    let (left, right) = photons.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+fn query(photons: &[Photon], axes: &[u8], p: Point3, radius_square: Precision, f: &mut impl FnMut(&Photon)) {
+    if photons.is_empty() {
+        return;
+    }
+
+    let mid = photons.len() / 2;
+    let node = &photons[mid];
+    if (node.p - p).len_square() <= radius_square {
+        f(node);
+    }
+
+    let axis = axes[mid] as i32;
+    let d = p[axis] - node.p[axis];
+    let (near, far) = if d < 0. { (0..mid, mid + 1..photons.len()) } else { (mid + 1..photons.len(), 0..mid) };
+
+    query(&photons[near.clone()], &axes[near], p, radius_square, f);
+    if d * d <= radius_square {
+        query(&photons[far.clone()], &axes[far], p, radius_square, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastrand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn finds_the_same_photons_as_a_linear_search() {
+        let mut rng = Rng::with_seed(5);
+        let photons: Vec<Photon> = (0..1000)
+            .map(|i| Photon {
+                p: Vec3::random_bounded(&mut rng, -1., 1.) * Vec3::new(4., 1., 0.5),
+                normal: Vec3::new(0., 1., 0.),
+                direction: Vec3::new(0., -1., 0.),
+                power: Color::new(i as Precision, 0., 0.),
+            })
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+
+        for _ in 0..50 {
+            let p = Vec3::random_bounded(&mut rng, -1., 1.);
+            let radius = 0.3 * rng.f32();
+
+            let mut found = Vec::new();
+            map.for_each_within(p, radius, |photon| found.push(photon.power.x()));
+            found.sort_by(|a, b| a.total_cmp(b));
+
+            let expected: Vec<Precision> =
+                photons.iter().filter(|photon| (photon.p - p).len() <= radius).map(|photon| photon.power.x()).collect();
+            assert_eq!(found, expected);
+        }
+    }
+}
@@ -3,3 +3,6 @@ pub mod image_formats;
 pub mod utility;
 pub mod figures;
 pub mod materials;
+pub mod textures;
+pub mod integrators;
+pub mod render;
@@ -1,11 +1,18 @@
 use std::rc::Rc;
 
+use fastrand::Rng;
 use ray_tracing::{
     figures::{
         camera::{Camera, DefocusSettings, ImageSettings, ViewSettings},
+        lens::{Aperture, TiltShift},
+        projection::Projection,
         sphere::Sphere,
     },
     materials::{dielectric::Dielectric, lambertian::Lambertian, material::Material, metal::Metal},
+    render::{
+        filter::Filter,
+        tonemap::DisplayTransform,
+    },
     utility::{
         color::Color,
         utils::random_f32,
@@ -13,6 +20,8 @@ use ray_tracing::{
     },
 };
 
+const SEED: u64 = 42;
+
 fn main() {
     // Camera
 
@@ -21,21 +30,41 @@ fn main() {
         image_width: 1200,
         samples_per_pixel: 500,
         max_depth: 50,
+        seed: SEED,
+        adaptive: None,
+        filter: Filter::default(),
+        display: DisplayTransform::default(),
+        region: None,
+        progressive: None,
+        stats: None,
+        aovs: None,
+        denoise: None,
+        russian_roulette: None,
+        clamp_indirect: None,
+        report_invalid_samples: false,
+        integrator: None,
     };
     let view_settings = ViewSettings {
         vfov: 20.,
         look_from: Point3::new(13., 2., 3.),
         look_at: Point3::new(0., 0., 0.),
         vup: Vec3::new(0., 1., 0.),
+        projection: Projection::Perspective,
+        stereo: None,
     };
     let defocus_settings = DefocusSettings {
         defocus_angle: 0.6,
         focus_dist: 10.,
+        aperture: Aperture::default(),
+        cat_eye: 0.,
+        tilt_shift: TiltShift::default(),
+        realistic_lens: None,
     };
     let camera = Camera::new(image_settings, view_settings, defocus_settings);
 
     // World
 
+    let mut rng = Rng::with_seed(SEED);
     let mut world = Vec::new();
 
     let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
@@ -50,24 +79,24 @@ fn main() {
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = fastrand::f32();
+            let choose_mat = rng.f32();
             let center = Point3::new(
-                a as Precision + 0.9 * fastrand::f32(),
+                a as Precision + 0.9 * rng.f32(),
                 0.2,
-                b as Precision + 0.9 * fastrand::f32(),
+                b as Precision + 0.9 * rng.f32(),
             );
 
             if (center - Point3::new(4., 0.2, 0.)).len() > 0.9 {
                 let sphere_material: Rc<dyn Material> = if choose_mat < 0.8 {
                     // diffuse.
 
-                    let albedo = Color::random() * Color::random();
+                    let albedo = Color::random(&mut rng) * Color::random(&mut rng);
                     Rc::new(Lambertian::new(albedo))
                 } else if choose_mat < 0.95 {
                     // metal.
 
-                    let albedo = Color::random_bounded(0.5, 1.);
-                    let fuzz = random_f32(0., 0.5);
+                    let albedo = Color::random_bounded(&mut rng, 0.5, 1.);
+                    let fuzz = random_f32(&mut rng, 0., 0.5);
                     Rc::new(Metal::new(albedo, fuzz))
                 } else {
                     // glass.
@@ -0,0 +1,103 @@
+use fastrand::Rng;
+
+use crate::{figures::hittable::HitRecord, utility::{color::Color, ray::Ray, vec3::{Precision, Vec3}}};
+
+use super::{
+    fresnel,
+    material::{Lobe, Material, ScatteredRay},
+    microfacet::{Frame, Microfacet},
+};
+
+/// Rough metal, reflecting off microfacets with the Fresnel term of its complex
+/// refraction index. Unlike `Metal`, it keeps the energy it reflects at every angle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    microfacet: Microfacet,
+}
+
+impl Conductor {
+    /// Metal with the complex refraction index `eta + i k`, per channel.
+    pub fn new(eta: Color, k: Color, microfacet: Microfacet) -> Self {
+        Self { eta, k, microfacet }
+    }
+
+    pub fn gold(microfacet: Microfacet) -> Self {
+        Self::new(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603), microfacet)
+    }
+
+    pub fn copper(microfacet: Microfacet) -> Self {
+        Self::new(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142), microfacet)
+    }
+
+    pub fn aluminium(microfacet: Microfacet) -> Self {
+        Self::new(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837), microfacet)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatteredRay> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(&-ray.direction().unit_vec());
+        if wo.z() <= 0. {
+            return None;
+        }
+
+        let h = self.microfacet.sample_visible(&wo, rng);
+        let wi = (-wo).reflect(h);
+        if wi.z() <= 0. {
+            return None;
+        }
+
+        // The density of the visible normals cancels out all but the Fresnel term and
+        // the shadowing of the reflected direction.
+        let fresnel = fresnel::conductor(wo.dot(&h), self.eta, self.k);
+        Some(ScatteredRay {
+            ray: Ray::new(rec.p, frame.to_world(&wi)),
+            attenuation: fresnel * (self.microfacet.g(&wo, &wi) / self.microfacet.g1(&wo)),
+            lobe: Lobe::Specular,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        if wo.z() <= 0. || wi.z() <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+
+        let h = (wo + wi).unit_vec();
+        let fresnel = fresnel::conductor(wo.dot(&h), self.eta, self.k);
+        fresnel * (self.microfacet.d(&h) * self.microfacet.g(&wo, &wi) / (4. * wo.z()))
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Precision {
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        if wo.z() <= 0. || wi.z() <= 0. {
+            return 0.;
+        }
+
+        let h = (wo + wi).unit_vec();
+        self.microfacet.visible_density(&wo, &h) / (4. * wo.dot(&h))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        fresnel::conductor(1., self.eta, self.k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::materials::{material::tests::assert_sample_matches_eval, microfacet::Distribution};
+
+    use super::*;
+
+    #[test]
+    fn scattering_agrees_with_the_brdf() {
+        for distribution in [Distribution::Ggx, Distribution::Beckmann] {
+            assert_sample_matches_eval(Rc::new(Conductor::gold(Microfacet::new(distribution, 0.5))), true);
+        }
+    }
+}
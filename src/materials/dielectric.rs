@@ -1,9 +1,12 @@
+use fastrand::Rng;
+
 use crate::{figures::hittable::HitRecord, utility::{color::Color, ray::Ray, vec3::Precision}};
 
-use super::material::{Material, ScatteredRay};
+use super::material::{BsdfSample, Lobe, Material, ScatteredRay};
 
 
 
+#[derive(Debug)]
 pub struct Dielectric {
     refraction_index: Precision,
 }
@@ -15,7 +18,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<ScatteredRay> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, _rng: &mut Rng) -> Option<ScatteredRay> {
         let attenuation = Color::new(1., 1., 1.);
         let ri = if rec.front_face { 1. / self.refraction_index } else { self.refraction_index };
 
@@ -33,6 +36,22 @@ impl Material for Dielectric {
 
         let scattered = Ray::new(rec.p, direction);
 
-        Some(ScatteredRay { ray: scattered, attenuation })
+        Some(ScatteredRay {
+            ray: scattered,
+            attenuation,
+            lobe: Lobe::Specular,
+        })
+    }
+
+    fn sample(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let scattered = self.scatter(ray, rec, rng)?;
+
+        Some(BsdfSample {
+            ray: scattered.ray,
+            weight: scattered.attenuation,
+            pdf: 0.,
+            lobe: scattered.lobe,
+            delta: true,
+        })
     }
 }
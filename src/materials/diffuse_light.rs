@@ -0,0 +1,35 @@
+use fastrand::Rng;
+
+use crate::{figures::hittable::HitRecord, utility::{color::Color, ray::Ray}};
+
+use super::material::{Material, ScatteredRay};
+
+/// Emits light evenly from the front of the surface and scatters nothing.
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, _rec: &HitRecord, _rng: &mut Rng) -> Option<ScatteredRay> {
+        None
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit
+        } else {
+            Color::new(0., 0., 0.)
+        }
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(0., 0., 0.)
+    }
+}
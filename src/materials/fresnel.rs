@@ -0,0 +1,69 @@
+use crate::utility::{color::Color, vec3::Precision};
+
+/// Fraction of unpolarized light reflected by the interface between two dielectrics, for
+/// light arriving at `cos_i` to the normal. `eta` is the refraction index beyond the
+/// interface over the one on the side of the light. 1 under total internal reflection.
+pub fn dielectric(cos_i: Precision, eta: Precision) -> Precision {
+    let cos_i = cos_i.clamp(0., 1.);
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+
+    let cos_t = (1. - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Schlick's approximation of the reflectance for light arriving at `cos_i` to the
+/// normal, from the reflectance `f0` at normal incidence.
+pub fn schlick(cos_i: Precision, f0: Color) -> Color {
+    f0 + (1. - cos_i.clamp(0., 1.)).powi(5) * (Color::new(1., 1., 1.) - f0)
+}
+
+/// Fraction of light reflected by a conductor with the complex refraction index
+/// `eta + i k`, per channel, for light arriving at `cos_i` to the normal.
+pub fn conductor(cos_i: Precision, eta: Color, k: Color) -> Color {
+    Color::new(
+        conductor_channel(cos_i, eta.x(), k.x()),
+        conductor_channel(cos_i, eta.y(), k.y()),
+        conductor_channel(cos_i, eta.z(), k.z()),
+    )
+}
+
+fn conductor_channel(cos_i: Precision, eta: Precision, k: Precision) -> Precision {
+    let cos2 = cos_i.clamp(0., 1.).powi(2);
+    let sin2 = 1. - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4. * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+    let t2 = 2. * cos_i.clamp(0., 1.) * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conductors_without_absorption_reflect_like_dielectrics() {
+        for cos_i in [0.05, 0.3, 0.7, 1.] {
+            let reflected = conductor(cos_i, Color::new(1.5, 1.5, 1.5), Color::new(0., 0., 0.));
+            assert!((reflected.x() - dielectric(cos_i, 1.5)).abs() < 1e-5, "{cos_i}");
+        }
+
+        assert!((dielectric(1., 1.5) - 0.04).abs() < 1e-6);
+        assert_eq!(dielectric(0.1, 1. / 1.5), 1.);
+    }
+}
@@ -1,6 +1,8 @@
-use crate::{figures::hittable::HitRecord, utility::{color::Color, ray::Ray, vec3::Vec3}};
+use fastrand::Rng;
 
-use super::material::{Material, ScatteredRay};
+use crate::{figures::hittable::HitRecord, utility::{color::Color, ray::Ray, utils::pi32, vec3::{Precision, Vec3}}};
+
+use super::material::{Lobe, Material, ScatteredRay};
 
 #[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
 pub struct Lambertian {
@@ -14,8 +16,8 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray: &Ray, rec: &HitRecord) -> Option<ScatteredRay> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vec();
+    fn scatter(&self, _ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatteredRay> {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vec(rng);
 
         // Catch degenerate scatter direction.
         if scatter_direction.near_zero() {
@@ -25,6 +27,20 @@ impl Material for Lambertian {
         Some(ScatteredRay {
             ray: Ray::new(rec.p, scatter_direction),
             attenuation: self.albedo,
+            lobe: Lobe::Diffuse,
         })
     }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo * self.pdf(ray, rec, direction)
+    }
+
+    fn pdf(&self, _ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Precision {
+        // `scatter` samples directions proportionally to the cosine.
+        direction.unit_vec().dot(&rec.normal).max(0.) / pi32
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
 }
@@ -0,0 +1,182 @@
+use std::rc::Rc;
+
+use fastrand::Rng;
+
+use crate::{
+    figures::hittable::HitRecord,
+    utility::{color::Color, ray::Ray, vec3::{Precision, Vec3}},
+};
+
+use super::{
+    fresnel,
+    material::{BsdfSample, Lobe, Material, ScatteredRay},
+    microfacet::{Frame, Microfacet},
+};
+
+/// A clear dielectric coat over any base material, like varnish over wood or the clear
+/// coat of car paint.
+///
+/// The coat reflects off its microfacets. Light that gets through it on the way in and on
+/// the way out scatters off the base, tinted by the coat for the length it travels
+/// through. Refraction into the coat does not bend the base's directions, and light
+/// bouncing between the coat and the base more than once is left out.
+#[derive(Debug, Clone)]
+pub struct Layered {
+    base: Rc<dyn Material>,
+    ior: Precision,
+    coat: Microfacet,
+    /// Fraction of light the coat lets through, crossing it straight in and back out.
+    tint: Color,
+}
+
+impl Layered {
+    pub fn new(base: Rc<dyn Material>, ior: Precision, coat: Microfacet, tint: Color) -> Self {
+        Self { base, ior, coat, tint }
+    }
+
+    /// A colorless coat.
+    pub fn clear(base: Rc<dyn Material>, ior: Precision, coat: Microfacet) -> Self {
+        Self::new(base, ior, coat, Color::new(1., 1., 1.))
+    }
+
+    /// Light the coat lets through to and back from the base, for directions at `cos_o`
+    /// and `cos_i` to the normal.
+    fn transmittance(&self, cos_o: Precision, cos_i: Precision) -> Color {
+        let (cos_o, cos_i) = (cos_o.abs().max(1e-4), cos_i.abs().max(1e-4));
+        let crossing = (1. - fresnel::dielectric(cos_o, self.ior)) * (1. - fresnel::dielectric(cos_i, self.ior));
+        let path = 0.5 * (1. / cos_o + 1. / cos_i);
+        let tint = Color::new(self.tint.x().powf(path), self.tint.y().powf(path), self.tint.z().powf(path));
+        crossing * tint
+    }
+
+    /// Chance of sampling the coat rather than the base, seen at `cos_o`.
+    fn coat_chance(&self, cos_o: Precision) -> Precision {
+        fresnel::dielectric(cos_o, self.ior).clamp(0.1, 0.9)
+    }
+
+    /// Light reflected off the coat itself, BSDF times the cosine.
+    fn eval_coat(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        if wo.z() <= 0. || wi.z() <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+
+        let h = (wo + wi).unit_vec();
+        let value = fresnel::dielectric(wo.dot(&h), self.ior) * self.coat.d(&h) * self.coat.g(&wo, &wi) / (4. * wo.z());
+        Color::new(value, value, value)
+    }
+}
+
+impl Material for Layered {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatteredRay> {
+        self.sample(ray, rec, rng).map(ScatteredRay::from)
+    }
+
+    fn sample(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(&-ray.direction().unit_vec());
+        if wo.z() <= 0. {
+            return None;
+        }
+
+        let coat_chance = self.coat_chance(wo.z());
+        let (direction, lobe) = if rng.f32() < coat_chance {
+            let h = self.coat.sample_visible(&wo, rng);
+            (frame.to_world(&(-wo).reflect(h)), Lobe::Specular)
+        } else {
+            let sample = self.base.sample(ray, rec, rng)?;
+            if sample.delta {
+                // The base's mirror directions can only be reached through the base.
+                let wi = frame.to_local(&sample.ray.direction().unit_vec());
+                let weight = sample.weight * self.transmittance(wo.z(), wi.z()) / (1. - coat_chance);
+                return Some(BsdfSample { weight, ..sample });
+            }
+            (*sample.ray.direction(), sample.lobe)
+        };
+
+        let pdf = self.pdf(ray, rec, &direction);
+        if pdf <= 0. {
+            return None;
+        }
+
+        Some(BsdfSample {
+            ray: Ray::new(rec.p, direction),
+            weight: self.eval(ray, rec, &direction) / pdf,
+            pdf,
+            lobe,
+            delta: false,
+        })
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.base.emitted(rec)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        if wo.z() <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+
+        let base = self.transmittance(wo.z(), wi.z()) * self.base.eval(ray, rec, direction);
+        base + self.eval_coat(ray, rec, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Precision {
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        if wo.z() <= 0. {
+            return 0.;
+        }
+
+        let coat_chance = self.coat_chance(wo.z());
+        let mut pdf = (1. - coat_chance) * self.base.pdf(ray, rec, direction);
+        if wi.z() > 0. {
+            let h = (wo + wi).unit_vec();
+            pdf += coat_chance * self.coat.visible_density(&wo, &h) / (4. * wo.dot(&h));
+        }
+        pdf
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.tint * self.base.albedo(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::{
+        lambertian::Lambertian,
+        material::tests::{assert_sample_matches_eval, floor_hit},
+        metal::Metal,
+        microfacet::Distribution,
+    };
+
+    use super::*;
+
+    #[test]
+    fn coats_keep_energy_and_agree_with_the_bsdf() {
+        // Varnished wood.
+        let varnished = Layered::new(
+            Rc::new(Lambertian::new(Color::new(0.6, 0.4, 0.2))),
+            1.5,
+            Microfacet::new(Distribution::Ggx, 0.3),
+            Color::new(0.9, 0.8, 0.7),
+        );
+        assert_sample_matches_eval(Rc::new(varnished), true);
+
+        // A white mirror under a clear coat keeps at most all the light.
+        let mirror = Rc::new(Metal::new(Color::new(1., 1., 1.), 0.));
+        let clear = Rc::new(Layered::clear(mirror, 1.5, Microfacet::new(Distribution::Ggx, 0.3)));
+        let (ray, rec) = floor_hit(clear.clone(), true);
+        let mut rng = Rng::with_seed(12);
+        let trials = 200_000;
+        let mut kept = Color::default();
+        for _ in 0..trials {
+            if let Some(sample) = clear.sample(&ray, &rec, &mut rng) {
+                kept += sample.weight;
+            }
+        }
+        let kept = kept / trials as Precision;
+        assert!(kept.x() <= 1.01 && kept.x() > 0.9, "{kept}");
+    }
+}
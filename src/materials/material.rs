@@ -1,20 +1,185 @@
+use std::fmt::Debug;
+
+use fastrand::Rng;
+
 use crate::{
     figures::hittable::HitRecord,
-    utility::{color::Color, ray::Ray},
+    utility::{color::Color, ray::Ray, vec3::{Precision, Vec3}},
 };
 
 use super::lambertian::Lambertian;
 
+/// Kind of reflection a scattered ray comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd)]
+pub enum Lobe {
+    #[default]
+    Diffuse,
+    /// Mirror-like reflection or refraction, including glossy reflection.
+    Specular,
+}
+
 #[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
 pub struct ScatteredRay {
     pub ray: Ray,
     pub attenuation: Color,
+    pub lobe: Lobe,
+}
+
+/// A scattered direction with everything integrators need to weigh it, see
+/// `Material::sample`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BsdfSample {
+    pub ray: Ray,
+    /// BSDF times the cosine over the density: the weight of the light arriving along
+    /// `ray`, like `ScatteredRay::attenuation`.
+    pub weight: Color,
+    /// Density per unit solid angle of sampling the direction, 0 for delta lobes.
+    pub pdf: Precision,
+    pub lobe: Lobe,
+    /// Whether the direction is the only one the lobe scatters to, like off a mirror or
+    /// through smooth glass. `eval` and `pdf` are 0 there, so only following the sampled
+    /// ray finds the light that comes from it.
+    pub delta: bool,
+}
+
+impl From<BsdfSample> for ScatteredRay {
+    fn from(sample: BsdfSample) -> Self {
+        Self {
+            ray: sample.ray,
+            attenuation: sample.weight,
+            lobe: sample.lobe,
+        }
+    }
 }
 
 pub fn default_material() -> Lambertian {
     Lambertian::default()
 }
 
-pub trait Material {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<ScatteredRay>;
+/// How a surface scatters and gives off light. `scatter` and `sample` pick directions,
+/// `eval` and `pdf` evaluate any pair of directions, so that integrators can sample lights
+/// and weigh strategies against each other.
+pub trait Material: Debug {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatteredRay>;
+
+    /// Scatters like `scatter`, along with the density of the direction and whether it
+    /// comes from a delta lobe. By default, the density comes from `pdf` and no lobe is a
+    /// delta one, so materials with delta lobes must override it.
+    fn sample(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let scattered = self.scatter(ray, rec, rng)?;
+        let pdf = self.pdf(ray, rec, scattered.ray.direction());
+
+        Some(BsdfSample {
+            ray: scattered.ray,
+            weight: scattered.attenuation,
+            pdf,
+            lobe: scattered.lobe,
+            delta: false,
+        })
+    }
+
+    /// Light given off by the surface.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::new(0., 0., 0.)
+    }
+
+    /// BSDF times the cosine between `direction` and the normal, for light scattered
+    /// towards where `ray` came from out of `direction`. Delta lobes are left out, so it
+    /// is black for materials that only scatter in discrete directions.
+    fn eval(&self, _ray: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::new(0., 0., 0.)
+    }
+
+    /// Probability density, per unit solid angle, of `scatter` sending `ray` towards
+    /// `direction`. Delta lobes are left out, like for `eval`.
+    fn pdf(&self, _ray: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Precision {
+        0.
+    }
+
+    /// Base color of the surface, as written to the albedo pass.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1., 1., 1.)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        materials::{dielectric::Dielectric, metal::Metal, principled::Principled},
+        utility::{utils::pi32, vec3::Point3},
+    };
+
+    use super::*;
+
+    /// A ray coming down at 45 degrees onto `material` on a floor, from outside or inside.
+    pub(crate) fn floor_hit(material: Rc<dyn Material>, front_face: bool) -> (Ray, HitRecord) {
+        let ray = Ray::new(Point3::new(-1., 1., 0.), Vec3::new(1., -1., 0.));
+        let rec = HitRecord {
+            normal: Vec3::new(0., 1., 0.),
+            front_face,
+            material,
+            ..Default::default()
+        };
+        (ray, rec)
+    }
+
+    /// Checks that the light `sample` keeps on average is the integral of `eval` over the
+    /// sphere, and that it only samples where `pdf` has density, which integrates to the
+    /// fraction of rays it scatters. For materials without delta lobes.
+    pub(crate) fn assert_sample_matches_eval(material: Rc<dyn Material>, front_face: bool) {
+        let (ray, rec) = floor_hit(material.clone(), front_face);
+        let mut rng = Rng::with_seed(6);
+        let trials = 400_000;
+
+        let mut kept = Color::default();
+        let mut density = 0.;
+        let mut scattered_rays = 0;
+        for _ in 0..trials {
+            if let Some(sample) = material.sample(&ray, &rec, &mut rng) {
+                assert!(!sample.delta && material.pdf(&ray, &rec, sample.ray.direction()) > 0., "{material:?}");
+                kept += sample.weight;
+                scattered_rays += 1;
+            }
+            let direction = Vec3::random_unit_vec(&mut rng);
+            kept -= 4. * pi32 * material.eval(&ray, &rec, &direction);
+            density += 4. * pi32 * material.pdf(&ray, &rec, &direction);
+        }
+
+        let difference = kept / trials as Precision;
+        assert!(difference.len() < 0.03, "{material:?} {front_face}: {difference}");
+        let (density, scattered) = (density / trials as Precision, scattered_rays as Precision / trials as Precision);
+        assert!((density - scattered).abs() < 0.03, "{material:?} {front_face}: {density} != {scattered}");
+    }
+
+    #[test]
+    fn samples_flag_delta_lobes() {
+        let materials: Vec<(Rc<dyn Material>, bool)> = vec![
+            (Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))), false),
+            (Rc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.)), true),
+            (Rc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.3)), false),
+            (Rc::new(Dielectric::new(1.5)), true),
+            (Rc::new(Principled::new(Default::default())), false),
+        ];
+        let mut rng = Rng::with_seed(2);
+
+        for (material, delta) in materials {
+            let (ray, rec) = floor_hit(material.clone(), true);
+
+            for _ in 0..100 {
+                let Some(sample) = material.sample(&ray, &rec, &mut rng) else {
+                    continue;
+                };
+                assert_eq!(sample.delta, delta, "{material:?}");
+
+                let pdf = material.pdf(&ray, &rec, sample.ray.direction());
+                if delta {
+                    assert_eq!((sample.pdf, pdf), (0., 0.), "{material:?}");
+                } else {
+                    assert!(sample.pdf > 0. && (sample.pdf - pdf).abs() <= 1e-4 * pdf, "{material:?}");
+                }
+            }
+        }
+    }
 }
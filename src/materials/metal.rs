@@ -1,6 +1,8 @@
-use crate::{figures::hittable::HitRecord, utility::{color::Color, interval::Interval, ray::Ray, vec3::{Precision, Vec3}}};
+use fastrand::Rng;
 
-use super::material::{Material, ScatteredRay};
+use crate::{figures::hittable::HitRecord, utility::{color::Color, interval::Interval, ray::Ray, utils::pi32, vec3::{Precision, Vec3}}};
+
+use super::material::{BsdfSample, Lobe, Material, ScatteredRay};
 
 #[derive(Debug)]
 pub struct Metal {
@@ -17,12 +19,100 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<ScatteredRay> {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatteredRay> {
         let reflected = ray.direction().reflect(rec.normal);
-        let reflected = reflected.unit_vec() + (self.fuzz * Vec3::random_unit_vec());
+        let reflected = reflected.unit_vec() + (self.fuzz * Vec3::random_unit_vec(rng));
         let scattered = Ray::new(rec.p, reflected);
         let attenuation = self.albedo;
 
-        (scattered.direction().dot(&rec.normal) > 0.).then_some(ScatteredRay { ray: scattered, attenuation })
+        (scattered.direction().dot(&rec.normal) > 0.).then_some(ScatteredRay {
+            ray: scattered,
+            attenuation,
+            lobe: Lobe::Specular,
+        })
+    }
+
+    fn sample(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let scattered = self.scatter(ray, rec, rng)?;
+
+        // Without fuzz, the mirror direction is the only one.
+        let delta = self.fuzz <= 0.;
+        Some(BsdfSample {
+            pdf: if delta { 0. } else { self.pdf(ray, rec, scattered.ray.direction()) },
+            ray: scattered.ray,
+            weight: scattered.attenuation,
+            lobe: scattered.lobe,
+            delta,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        // `scatter` keeps the albedo as is, so the BSDF times the cosine is the albedo
+        // times the density.
+        self.albedo * self.pdf(ray, rec, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Precision {
+        if self.fuzz <= 0. {
+            return 0.;
+        }
+
+        let direction = direction.unit_vec();
+        if direction.dot(&rec.normal) <= 0. {
+            return 0.;
+        }
+
+        // `scatter` picks a point uniformly on the sphere of radius `fuzz` around the
+        // tip of the mirror direction. Sum the density of the points along `direction`,
+        // converted from area to solid angle.
+        let reflected = ray.direction().reflect(rec.normal).unit_vec();
+        let h = direction.dot(&reflected);
+        let discriminant = h * h - 1. + self.fuzz * self.fuzz;
+        if discriminant < 0. {
+            return 0.;
+        }
+
+        let area_density = 1. / (4. * pi32 * self.fuzz * self.fuzz);
+        let sqrtd = discriminant.sqrt();
+        [h - sqrtd, h + sqrtd]
+            .into_iter()
+            .filter(|&t| t > 1e-6)
+            .map(|t| {
+                let cos = (direction.dot(&(t * direction - reflected)) / self.fuzz).abs();
+                area_density * t * t / cos.max(1e-6)
+            })
+            .sum()
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::materials::material::tests::floor_hit;
+
+    use super::*;
+
+    #[test]
+    fn density_matches_scattering() {
+        let metal = Metal::new(Color::new(1., 1., 1.), 0.4);
+        let (ray, rec) = floor_hit(Rc::new(Metal::new(Color::new(1., 1., 1.), 0.4)), true);
+        let mut rng = Rng::with_seed(5);
+
+        // Integrating the density over all directions gives the chance of not
+        // scattering into the surface.
+        let trials = 200_000;
+        let scattered = (0..trials).filter(|_| metal.scatter(&ray, &rec, &mut rng).is_some()).count();
+        let integral: Precision = (0..trials)
+            .map(|_| 4. * pi32 * metal.pdf(&ray, &rec, &Vec3::random_unit_vec(&mut rng)))
+            .sum::<Precision>()
+            / trials as Precision;
+
+        let expected = scattered as Precision / trials as Precision;
+        assert!((integral - expected).abs() < 0.03, "{integral} != {expected}");
     }
 }
@@ -0,0 +1,311 @@
+use fastrand::Rng;
+
+use crate::{
+    figures::hittable::HitRecord,
+    utility::{ray::Ray, utils::pi32, vec3::{Precision, Vec3}},
+};
+
+/// Distribution of the slopes of microfacets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Distribution {
+    /// Trowbridge-Reitz, with long tails that give highlights a glow.
+    #[default]
+    Ggx,
+    /// Gaussian slopes, with sharper highlights.
+    Beckmann,
+}
+
+/// Isotropic microfacet distribution, in the shading space of a `Frame`, where the
+/// normal is `+z`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Microfacet {
+    pub distribution: Distribution,
+    /// Width of the distribution.
+    pub alpha: Precision,
+}
+
+impl Microfacet {
+    /// Distribution for a perceptual `roughness` between 0 and 1, squared into the width
+    /// like glTF does. Smooth surfaces keep a tiny width, so that they stay glossy.
+    pub fn new(distribution: Distribution, roughness: Precision) -> Self {
+        let alpha = roughness.clamp(0., 1.).powi(2).max(1e-3);
+
+        Self { distribution, alpha }
+    }
+
+    /// Density of the microfacet normals, per solid angle and unit area of the surface.
+    pub fn d(&self, h: &Vec3) -> Precision {
+        let cos2 = h.z() * h.z();
+        if h.z() <= 0. {
+            return 0.;
+        }
+
+        let tan2 = (1. - cos2) / cos2;
+        let a2 = self.alpha * self.alpha;
+        match self.distribution {
+            Distribution::Ggx => a2 / (pi32 * cos2 * cos2 * (a2 + tan2).powi(2)),
+            Distribution::Beckmann => (-tan2 / a2).exp() / (pi32 * a2 * cos2 * cos2),
+        }
+    }
+
+    /// Smith's auxiliary function: the area of microfacets facing away from `w` over the
+    /// area of those facing it.
+    pub fn lambda(&self, w: &Vec3) -> Precision {
+        let cos2 = w.z() * w.z();
+        if cos2 >= 1. {
+            return 0.;
+        }
+
+        let tan2 = (1. - cos2) / cos2;
+        match self.distribution {
+            Distribution::Ggx => 0.5 * ((1. + self.alpha * self.alpha * tan2).sqrt() - 1.),
+            Distribution::Beckmann => {
+                let a = 1. / (self.alpha * tan2.sqrt());
+                if a >= 1.6 {
+                    0.
+                } else {
+                    (1. - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+                }
+            }
+        }
+    }
+
+    /// Fraction of the microfacets seen from `w` that are not hidden by others.
+    pub fn g1(&self, w: &Vec3) -> Precision {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets seen from both `wo` and `wi`, height correlated.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> Precision {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Picks a microfacet normal among those seen from `wo`, weighted by their projected
+    /// area, see `visible_density`. `wo` must be above the surface.
+    pub fn sample_visible(&self, wo: &Vec3, rng: &mut Rng) -> Vec3 {
+        match self.distribution {
+            Distribution::Ggx => self.sample_visible_ggx(wo, rng.f32(), rng.f32()),
+            Distribution::Beckmann => self.sample_visible_beckmann(wo, rng.f32(), rng.f32()),
+        }
+    }
+
+    /// Density per solid angle of `sample_visible` picking `h` from `wo`.
+    pub fn visible_density(&self, wo: &Vec3, h: &Vec3) -> Precision {
+        if wo.z() <= 0. {
+            return 0.;
+        }
+
+        self.g1(wo) * wo.dot(h).max(0.) * self.d(h) / wo.z()
+    }
+
+    /// Heitz, "Sampling the GGX Distribution of Visible Normals", 2018.
+    fn sample_visible_ggx(&self, wo: &Vec3, u1: Precision, u2: Precision) -> Vec3 {
+        // Stretch the view so that the distribution becomes a hemisphere.
+        let v = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).unit_vec();
+
+        let len2 = v.x() * v.x() + v.y() * v.y();
+        let t1 = if len2 > 0. {
+            Vec3::new(-v.y(), v.x(), 0.) / len2.sqrt()
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let t2 = v.cross(&t1);
+
+        // Sample the projected half disk, and lift it onto the hemisphere.
+        let r = u1.sqrt();
+        let phi = 2. * pi32 * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + v.z());
+        let p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * v;
+
+        Vec3::new(self.alpha * n.x(), self.alpha * n.y(), n.z().max(0.)).unit_vec()
+    }
+
+    /// Jakob, "An Improved Visible Normal Sampling Routine for the Beckmann
+    /// Distribution", 2014, as in pbrt.
+    fn sample_visible_beckmann(&self, wo: &Vec3, u1: Precision, u2: Precision) -> Vec3 {
+        let v = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).unit_vec();
+        let (slope_x, slope_y) = beckmann_slopes(v.z(), u1, u2);
+
+        // Rotate the slopes to the view, and unstretch them.
+        let sin_theta = (1. - v.z() * v.z()).max(0.).sqrt();
+        let (cos_phi, sin_phi) = if sin_theta > 0. {
+            ((v.x() / sin_theta).clamp(-1., 1.), (v.y() / sin_theta).clamp(-1., 1.))
+        } else {
+            (1., 0.)
+        };
+        let x = self.alpha * (cos_phi * slope_x - sin_phi * slope_y);
+        let y = self.alpha * (sin_phi * slope_x + cos_phi * slope_y);
+
+        Vec3::new(-x, -y, 1.).unit_vec()
+    }
+}
+
+/// Slopes of a visible normal of the Beckmann distribution of width 1, seen at
+/// `cos_theta` in the `xz` plane.
+fn beckmann_slopes(cos_theta: Precision, u1: Precision, u2: Precision) -> (Precision, Precision) {
+    if cos_theta > 0.9999 {
+        let r = (-(1. - u1).ln()).sqrt();
+        let phi = 2. * pi32 * u2;
+        return (r * phi.cos(), r * phi.sin());
+    }
+
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    let tan_theta = sin_theta / cos_theta;
+    let cot_theta = 1. / tan_theta;
+    let inv_sqrt_pi = 1. / pi32.sqrt();
+
+    // Invert the cumulative distribution of the x slope with Newton steps, kept within
+    // the bracket [a, c].
+    let mut a = -1.;
+    let mut c = erf(cot_theta);
+    let u = u1.max(1e-6);
+    let theta = cos_theta.acos();
+    let fit = 1. + theta * (-0.876 + theta * (0.4265 - 0.0594 * theta));
+    let mut b = c - (1. + c) * (1. - u).powf(fit);
+    let normalization = 1. / (1. + c + inv_sqrt_pi * tan_theta * (-cot_theta * cot_theta).exp());
+
+    for _ in 0..10 {
+        if !(a..=c).contains(&b) {
+            b = 0.5 * (a + c);
+        }
+        let inv_erf = erf_inv(b);
+        let value = normalization * (1. + b + inv_sqrt_pi * tan_theta * (-inv_erf * inv_erf).exp()) - u;
+        if value.abs() < 1e-5 {
+            break;
+        }
+        let derivative = normalization * (1. - inv_erf * tan_theta);
+        if value > 0. {
+            c = b;
+        } else {
+            a = b;
+        }
+        b -= value / derivative;
+    }
+
+    (erf_inv(b), erf_inv(2. * u2.max(1e-6) - 1.))
+}
+
+/// Error function, after Abramowitz and Stegun 7.1.26.
+fn erf(x: Precision) -> Precision {
+    let t = 1. / (1. + 0.327_591_1 * x.abs());
+    let poly = ((((1.061_405_4 * t - 1.453_152) * t + 1.421_413_8) * t - 0.284_496_73) * t + 0.254_829_6) * t;
+    (1. - poly * (-x * x).exp()).copysign(x)
+}
+
+/// Inverse of the error function, after Giles.
+fn erf_inv(x: Precision) -> Precision {
+    let x = x.clamp(-0.99999, 0.99999);
+    let w = -((1. - x) * (1. + x)).ln();
+    let p = if w < 5. {
+        let w = w - 2.5;
+        [
+            3.432_739_4e-7,
+            -3.523_387_7e-6,
+            -4.391_506_5e-6,
+            2.185_808_7e-4,
+            -1.253_725e-3,
+            -4.177_681_6e-3,
+            0.246_640_73,
+            1.501_409_4,
+        ]
+        .into_iter()
+        .fold(2.810_226_4e-8, |p, coefficient| coefficient + p * w)
+    } else {
+        let w = w.sqrt() - 3.;
+        [
+            1.009_505_6e-4,
+            1.349_343_2e-3,
+            -3.673_428_4e-3,
+            5.739_507_7e-3,
+            -7.622_461e-3,
+            9.438_870_5e-3,
+            1.001_674,
+            2.832_976_8,
+        ]
+        .into_iter()
+        .fold(-2.002_142_6e-4, |p, coefficient| coefficient + p * w)
+    };
+    p * x
+}
+
+/// Orthonormal basis around a normal, for going to and from shading space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    s: Vec3,
+    t: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    /// Frame around the unit vector `n`, after Duff et al.
+    pub fn new(n: Vec3) -> Self {
+        let sign = if n.z() >= 0. { 1. } else { -1. };
+        let a = -1. / (sign + n.z());
+        let b = n.x() * n.y() * a;
+        let s = Vec3::new(1. + sign * n.x() * n.x() * a, sign * b, -sign * n.x());
+        let t = Vec3::new(b, sign + n.y() * n.y() * a, -n.y());
+
+        Self { s, t, n }
+    }
+
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.s), v.dot(&self.t), v.dot(&self.n))
+    }
+
+    pub fn to_world(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.s + v.y() * self.t + v.z() * self.n
+    }
+
+    /// Directions towards where `ray` came from and `direction`, in the shading space
+    /// around the normal at `rec`.
+    pub fn local(ray: &Ray, rec: &HitRecord, direction: &Vec3) -> (Vec3, Vec3) {
+        let frame = Self::new(rec.normal);
+        (frame.to_local(&-ray.direction().unit_vec()), frame.to_local(&direction.unit_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visible_normals_follow_their_density() {
+        let mut rng = Rng::with_seed(8);
+        let trials = 200_000;
+
+        for distribution in [Distribution::Ggx, Distribution::Beckmann] {
+            let microfacet = Microfacet::new(distribution, 0.6);
+            let wo = Vec3::new(0.6, 0., 0.8);
+
+            // The projected area of the microfacets is the area of the surface.
+            let projected: Precision = (0..trials)
+                .map(|_| {
+                    let h = Vec3::random_unit_vec(&mut rng);
+                    4. * pi32 * microfacet.d(&h) * h.z().max(0.)
+                })
+                .sum::<Precision>()
+                / trials as Precision;
+            assert!((projected - 1.).abs() < 0.03, "{distribution:?}: {projected}");
+
+            // The sampled normals lean like their density says, here towards the view.
+            let sampled: Vec3 = (0..trials)
+                .map(|_| {
+                    let h = microfacet.sample_visible(&wo, &mut rng);
+                    assert!(h.z() >= 0. && (h.len() - 1.).abs() < 1e-4);
+                    h
+                })
+                .fold(Vec3::default(), |sum, h| sum + h)
+                / trials as Precision;
+            let expected: Vec3 = (0..trials)
+                .map(|_| {
+                    let h = Vec3::random_unit_vec(&mut rng);
+                    4. * pi32 * microfacet.visible_density(&wo, &h) * h
+                })
+                .fold(Vec3::default(), |sum, h| sum + h)
+                / trials as Precision;
+            assert!((sampled - expected).len() < 0.02, "{distribution:?}: {sampled} != {expected}");
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::rc::Rc;
+
+use fastrand::Rng;
+
+use crate::{
+    figures::hittable::HitRecord,
+    textures::texture::Texture,
+    utility::{color::Color, ray::Ray, vec3::{Precision, Vec3}},
+};
+
+use super::{
+    fresnel,
+    material::{BsdfSample, Material, ScatteredRay},
+};
+
+/// How much of the second material a `Mix` takes, between 0 and 1.
+#[derive(Debug, Clone)]
+pub enum MixWeight {
+    Constant(Precision),
+    /// First channel of a texture.
+    Texture(Rc<dyn Texture>),
+    /// Reflectance of a dielectric with this refraction index, seen from the ray, so that
+    /// the second material shows at grazing angles.
+    Fresnel(Precision),
+}
+
+/// Blend of two materials, like dust over metal.
+#[derive(Debug, Clone)]
+pub struct Mix {
+    first: Rc<dyn Material>,
+    second: Rc<dyn Material>,
+    weight: MixWeight,
+}
+
+impl Mix {
+    pub fn new(first: Rc<dyn Material>, second: Rc<dyn Material>, weight: MixWeight) -> Self {
+        Self { first, second, weight }
+    }
+
+    /// Share of the second material at the hit point.
+    fn weight(&self, ray: &Ray, rec: &HitRecord) -> Precision {
+        let weight = match &self.weight {
+            MixWeight::Constant(weight) => *weight,
+            MixWeight::Texture(texture) => texture.scalar_at(rec),
+            MixWeight::Fresnel(ior) => {
+                let cos = -ray.direction().unit_vec().dot(&rec.normal);
+                let eta = if rec.front_face { *ior } else { 1. / *ior };
+                fresnel::dielectric(cos, eta)
+            }
+        };
+        weight.clamp(0., 1.)
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatteredRay> {
+        self.sample(ray, rec, rng).map(ScatteredRay::from)
+    }
+
+    fn sample(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let weight = self.weight(ray, rec);
+        let picked = if rng.f32() < weight { &self.second } else { &self.first };
+        let sample = picked.sample(ray, rec, rng)?;
+
+        // Picking the material with the chance of its share cancels it out of delta
+        // lobes. Other directions are weighed with both materials.
+        if sample.delta {
+            return Some(sample);
+        }
+
+        let pdf = self.pdf(ray, rec, sample.ray.direction());
+        if pdf <= 0. {
+            return None;
+        }
+        Some(BsdfSample {
+            weight: self.eval(ray, rec, sample.ray.direction()) / pdf,
+            pdf,
+            ..sample
+        })
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        // Without a ray, the Fresnel weight is taken at normal incidence.
+        let ray = Ray::new(rec.p + rec.normal, -rec.normal);
+        let weight = self.weight(&ray, rec);
+        (1. - weight) * self.first.emitted(rec) + weight * self.second.emitted(rec)
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let weight = self.weight(ray, rec);
+        (1. - weight) * self.first.eval(ray, rec, direction) + weight * self.second.eval(ray, rec, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Precision {
+        let weight = self.weight(ray, rec);
+        (1. - weight) * self.first.pdf(ray, rec, direction) + weight * self.second.pdf(ray, rec, direction)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let ray = Ray::new(rec.p + rec.normal, -rec.normal);
+        let weight = self.weight(&ray, rec);
+        (1. - weight) * self.first.albedo(rec) + weight * self.second.albedo(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        materials::{
+            lambertian::Lambertian,
+            material::tests::floor_hit,
+            metal::Metal,
+        },
+        utility::vec3::Point3,
+    };
+
+    use super::*;
+
+    #[test]
+    fn blends_the_light_of_both_materials() {
+        let dusty = Mix::new(
+            Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            Rc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.)),
+            MixWeight::Constant(0.25),
+        );
+        let (ray, rec) = floor_hit(Rc::new(dusty.clone()), true);
+        let mut rng = Rng::with_seed(4);
+
+        let trials = 100_000;
+        let mut kept = 0.;
+        let mut mirrored = 0;
+        for _ in 0..trials {
+            let sample = dusty.sample(&ray, &rec, &mut rng).unwrap();
+            kept += sample.weight.x();
+            if sample.delta {
+                mirrored += 1;
+                assert!((sample.ray.direction().unit_vec() - Vec3::new(1., 1., 0.).unit_vec()).len() < 1e-6);
+            }
+        }
+
+        let kept = kept / trials as Precision;
+        let mirrored = mirrored as Precision / trials as Precision;
+        assert!((kept - (0.75 * 0.5 + 0.25 * 0.9)).abs() < 0.01, "{kept}");
+        assert!((mirrored - 0.25).abs() < 0.01, "{mirrored}");
+
+        // Seen head on, glass reflects 4%, at grazing angles nearly everything.
+        let fresnel = Mix::new(rec.material.clone(), rec.material.clone(), MixWeight::Fresnel(1.5));
+        let down = Ray::new(Point3::new(0., 1., 0.), Vec3::new(0., -1., 0.));
+        let grazing = Ray::new(Point3::new(-1., 0.01, 0.), Vec3::new(1., -0.01, 0.));
+        assert!((fresnel.weight(&down, &rec) - 0.04).abs() < 1e-6);
+        assert!(fresnel.weight(&grazing, &rec) > 0.9);
+    }
+}
@@ -2,3 +2,11 @@ pub mod material;
 pub mod lambertian;
 pub mod metal;
 pub mod dielectric;
+pub mod diffuse_light;
+pub mod fresnel;
+pub mod microfacet;
+pub mod conductor;
+pub mod rough_dielectric;
+pub mod principled;
+pub mod mix;
+pub mod layered;
@@ -0,0 +1,378 @@
+use std::rc::Rc;
+
+use fastrand::Rng;
+
+use crate::{
+    figures::hittable::HitRecord,
+    textures::texture::{Channel, ColorChannel, Scale, SolidColor, Texture},
+    utility::{
+        color::Color,
+        ray::Ray,
+        utils::pi32,
+        vec3::{Precision, Vec3},
+    },
+};
+
+use super::{
+    fresnel,
+    material::{BsdfSample, Lobe, Material, ScatteredRay},
+    microfacet::{Distribution, Frame, Microfacet},
+    rough_dielectric::RoughDielectric,
+};
+
+/// Parameters of the principled material. Scalar parameters are between 0 and 1, and
+/// read the first channel of their texture.
+#[derive(Debug, Clone)]
+pub struct PrincipledSettings {
+    /// Diffuse color of dielectrics, reflectance of metals and tint of transmitted light.
+    pub base_color: Rc<dyn Texture>,
+    /// Blends from a dielectric to a metal.
+    pub metallic: Rc<dyn Texture>,
+    /// Perceptual roughness of the specular reflection and transmission.
+    pub roughness: Rc<dyn Texture>,
+    /// Reflectance of dielectrics at normal incidence, scaled so that 0.5 gives the 4% of
+    /// glTF.
+    pub specular: Rc<dyn Texture>,
+    /// Soft reflection at grazing angles, for cloth.
+    pub sheen: Rc<dyn Texture>,
+    /// Tints the sheen from white to the hue of the base color.
+    pub sheen_tint: Rc<dyn Texture>,
+    /// Strength of a clear varnish over the whole material.
+    pub clearcoat: Rc<dyn Texture>,
+    pub clearcoat_roughness: Rc<dyn Texture>,
+    /// Blends dielectrics from diffuse to glass.
+    pub transmission: Rc<dyn Texture>,
+    /// Flattens the diffuse reflection the way light scattering under the surface does.
+    pub subsurface: Rc<dyn Texture>,
+    /// Light given off by the front of the surface.
+    pub emission: Rc<dyn Texture>,
+    /// Refraction index of the dielectric, for transmission.
+    pub ior: Precision,
+}
+
+impl Default for PrincipledSettings {
+    fn default() -> Self {
+        let base_color = Rc::new(SolidColor::gray(0.8));
+        let metallic = Rc::new(SolidColor::gray(0.));
+        let roughness = Rc::new(SolidColor::gray(0.5));
+        let specular = Rc::new(SolidColor::gray(0.5));
+        let sheen = Rc::new(SolidColor::gray(0.));
+        let sheen_tint = Rc::new(SolidColor::gray(0.5));
+        let clearcoat = Rc::new(SolidColor::gray(0.));
+        let clearcoat_roughness = Rc::new(SolidColor::gray(0.1));
+        let transmission = Rc::new(SolidColor::gray(0.));
+        let subsurface = Rc::new(SolidColor::gray(0.));
+        let emission = Rc::new(SolidColor::gray(0.));
+        let ior = 1.5;
+
+        Self {
+            base_color,
+            metallic,
+            roughness,
+            specular,
+            sheen,
+            sheen_tint,
+            clearcoat,
+            clearcoat_roughness,
+            transmission,
+            subsurface,
+            emission,
+            ior,
+        }
+    }
+}
+
+impl PrincipledSettings {
+    /// Metallic and roughness from a glTF metallic-roughness texture, which packs
+    /// roughness in green and metallic in blue, scaled by their factors. Such images are
+    /// data rather than colors, see `ImageTexture::data`.
+    pub fn with_metallic_roughness(
+        self,
+        texture: Rc<dyn Texture>,
+        metallic_factor: Precision,
+        roughness_factor: Precision,
+    ) -> Self {
+        let factor = |f: Precision| Color::new(f, f, f);
+        let metallic = Rc::new(Channel::new(texture.clone(), ColorChannel::Blue));
+        let roughness = Rc::new(Channel::new(texture, ColorChannel::Green));
+        let metallic = Rc::new(Scale::new(metallic, factor(metallic_factor)));
+        let roughness = Rc::new(Scale::new(roughness, factor(roughness_factor)));
+
+        Self {
+            metallic,
+            roughness,
+            ..self
+        }
+    }
+}
+
+/// Principled material after Burley's "Physically Based Shading at Disney", following
+/// the glTF metallic-roughness conventions: a diffuse base with sheen, a GGX specular
+/// reflection, rough glass for transmission and a clear coat on top, blended by texturable
+/// parameters.
+///
+/// Scattering picks one of the lobes and weighs the direction with the BSDF and density of
+/// them all, so `scatter`, `eval` and `pdf` always agree.
+#[derive(Debug, Clone)]
+pub struct Principled {
+    settings: PrincipledSettings,
+}
+
+/// Parameters looked up at a hit point.
+struct Params {
+    base_color: Color,
+    roughness: Precision,
+    f0: Color,
+    sheen: Color,
+    clearcoat: Precision,
+    subsurface: Precision,
+    diffuse_weight: Precision,
+    specular_weight: Precision,
+    transmission_weight: Precision,
+    specular: Microfacet,
+    coat: Microfacet,
+    glass: RoughDielectric,
+    /// Whether the ray travels inside the glass, where only the interface matters.
+    inside: bool,
+}
+
+/// Chances of sampling each lobe.
+struct LobeChances {
+    diffuse: Precision,
+    specular: Precision,
+    transmission: Precision,
+    coat: Precision,
+}
+
+/// Reflectance of the clear coat, a dielectric of index 1.5.
+const COAT_F0: Precision = 0.04;
+
+impl Principled {
+    pub fn new(settings: PrincipledSettings) -> Self {
+        Self { settings }
+    }
+
+    fn params(&self, rec: &HitRecord) -> Params {
+        let s = &self.settings;
+        let base_color = s.base_color.at(rec);
+        let metallic = s.metallic.scalar_at(rec).clamp(0., 1.);
+        let roughness = s.roughness.scalar_at(rec).clamp(0., 1.);
+        let transmission = s.transmission.scalar_at(rec).clamp(0., 1.);
+
+        let luminance = base_color.luminance();
+        let tint = if luminance > 0. { base_color / luminance } else { Color::new(1., 1., 1.) };
+        let white = Color::new(1., 1., 1.);
+        let sheen_tint = s.sheen_tint.scalar_at(rec).clamp(0., 1.);
+        let dielectric_f0 = 0.08 * s.specular.scalar_at(rec).max(0.);
+
+        let transmission_weight = (1. - metallic) * transmission;
+        Params {
+            base_color,
+            roughness,
+            f0: (1. - metallic) * Color::new(dielectric_f0, dielectric_f0, dielectric_f0) + metallic * base_color,
+            sheen: s.sheen.scalar_at(rec).max(0.) * ((1. - sheen_tint) * white + sheen_tint * tint),
+            clearcoat: s.clearcoat.scalar_at(rec).clamp(0., 1.),
+            subsurface: s.subsurface.scalar_at(rec).clamp(0., 1.),
+            diffuse_weight: (1. - metallic) * (1. - transmission),
+            specular_weight: 1. - transmission_weight,
+            transmission_weight,
+            specular: Microfacet::new(Distribution::Ggx, roughness),
+            coat: Microfacet::new(Distribution::Ggx, s.clearcoat_roughness.scalar_at(rec)),
+            glass: RoughDielectric::new(s.ior, Microfacet::new(Distribution::Ggx, roughness)),
+            inside: !rec.front_face && transmission_weight > 0.,
+        }
+    }
+
+    fn chances(p: &Params, wo: &Vec3) -> LobeChances {
+        let diffuse = p.diffuse_weight * (p.base_color.luminance() + p.sheen.luminance());
+        let specular = p.specular_weight * fresnel::schlick(wo.z(), p.f0).luminance();
+        let transmission = p.transmission_weight;
+        let coat = p.clearcoat * fresnel::schlick(wo.z(), Color::new(COAT_F0, COAT_F0, COAT_F0)).x();
+
+        let total = diffuse + specular + transmission + coat;
+        if total <= 0. {
+            return LobeChances { diffuse: 0., specular: 0., transmission: 0., coat: 0. };
+        }
+
+        LobeChances {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            transmission: transmission / total,
+            coat: coat / total,
+        }
+    }
+
+    /// Light the coat lets through to the layers below, seen from `wo`.
+    fn coat_transmittance(p: &Params, wo: &Vec3) -> Precision {
+        1. - p.clearcoat * fresnel::schlick(wo.z(), Color::new(COAT_F0, COAT_F0, COAT_F0)).x()
+    }
+
+    /// Burley's diffuse, blended with its subsurface approximation, plus sheen. BSDF
+    /// times the cosine.
+    fn diffuse(p: &Params, wo: &Vec3, wi: &Vec3, h: &Vec3) -> Color {
+        let cos_d = wi.dot(h);
+        let fl = (1. - wi.z()).powi(5);
+        let fv = (1. - wo.z()).powi(5);
+
+        let fd90 = 0.5 + 2. * p.roughness * cos_d * cos_d;
+        let fd = (1. + (fd90 - 1.) * fl) * (1. + (fd90 - 1.) * fv);
+        let fss90 = p.roughness * cos_d * cos_d;
+        let fss = (1. + (fss90 - 1.) * fl) * (1. + (fss90 - 1.) * fv);
+        let ss = 1.25 * (fss * (1. / (wi.z() + wo.z()) - 0.5) + 0.5);
+
+        let diffuse = p.base_color * (((1. - p.subsurface) * fd + p.subsurface * ss) / pi32);
+        let sheen = p.sheen * (1. - cos_d).powi(5);
+        (diffuse + sheen) * wi.z()
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatteredRay> {
+        self.sample(ray, rec, rng).map(ScatteredRay::from)
+    }
+
+    fn sample(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<BsdfSample> {
+        let p = self.params(rec);
+        if p.inside {
+            return p.glass.sample(ray, rec, rng);
+        }
+
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(&-ray.direction().unit_vec());
+        if wo.z() <= 0. {
+            return None;
+        }
+
+        let chances = Self::chances(&p, &wo);
+        let u = rng.f32();
+        let (direction, lobe) = if u < chances.diffuse {
+            let mut direction = rec.normal + Vec3::random_unit_vec(rng);
+            if direction.near_zero() {
+                direction = rec.normal;
+            }
+            (direction, Lobe::Diffuse)
+        } else if u < chances.diffuse + chances.specular {
+            let h = p.specular.sample_visible(&wo, rng);
+            (frame.to_world(&(-wo).reflect(h)), Lobe::Specular)
+        } else if u < chances.diffuse + chances.specular + chances.coat {
+            let h = p.coat.sample_visible(&wo, rng);
+            (frame.to_world(&(-wo).reflect(h)), Lobe::Specular)
+        } else {
+            (*p.glass.scatter(ray, rec, rng)?.ray.direction(), Lobe::Specular)
+        };
+
+        let pdf = self.pdf(ray, rec, &direction);
+        if pdf <= 0. {
+            return None;
+        }
+
+        Some(BsdfSample {
+            ray: Ray::new(rec.p, direction),
+            weight: self.eval(ray, rec, &direction) / pdf,
+            pdf,
+            lobe,
+            delta: false,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let p = self.params(rec);
+        if p.inside {
+            return p.glass.eval(ray, rec, direction);
+        }
+
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        if wo.z() <= 0. || wi.z() == 0. {
+            return Color::new(0., 0., 0.);
+        }
+
+        let below = Self::coat_transmittance(&p, &wo);
+        if wi.z() < 0. {
+            return below * p.transmission_weight * p.base_color * p.glass.eval(ray, rec, direction);
+        }
+
+        let h = (wo + wi).unit_vec();
+        let diffuse = p.diffuse_weight * Self::diffuse(&p, &wo, &wi, &h);
+        let specular = fresnel::schlick(wo.dot(&h), p.f0)
+            * (p.specular_weight * p.specular.d(&h) * p.specular.g(&wo, &wi) / (4. * wo.z()));
+        let glass = p.transmission_weight * p.glass.eval(ray, rec, direction);
+        let coat = fresnel::schlick(wo.dot(&h), Color::new(COAT_F0, COAT_F0, COAT_F0))
+            * (p.clearcoat * p.coat.d(&h) * p.coat.g(&wo, &wi) / (4. * wo.z()));
+
+        below * (diffuse + specular + glass) + coat
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Precision {
+        let p = self.params(rec);
+        if p.inside {
+            return p.glass.pdf(ray, rec, direction);
+        }
+
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        if wo.z() <= 0. {
+            return 0.;
+        }
+
+        let chances = Self::chances(&p, &wo);
+        let mut pdf = chances.transmission * p.glass.pdf(ray, rec, direction);
+        if wi.z() > 0. {
+            let h = (wo + wi).unit_vec();
+            pdf += chances.diffuse * wi.z() / pi32;
+            pdf += chances.specular * p.specular.visible_density(&wo, &h) / (4. * wo.dot(&h));
+            pdf += chances.coat * p.coat.visible_density(&wo, &h) / (4. * wo.dot(&h));
+        }
+        pdf
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.settings.emission.at(rec)
+        } else {
+            Color::new(0., 0., 0.)
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.settings.base_color.at(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::material::tests::assert_sample_matches_eval;
+
+    use super::*;
+
+    #[test]
+    fn scattering_agrees_with_the_bsdf() {
+        let gray = |v| Rc::new(SolidColor::gray(v)) as Rc<dyn Texture>;
+        let principled = Rc::new(Principled::new(
+            PrincipledSettings {
+                base_color: Rc::new(SolidColor::new(Color::new(0.8, 0.4, 0.2))),
+                specular: gray(0.6),
+                sheen: gray(0.5),
+                clearcoat: gray(0.5),
+                clearcoat_roughness: gray(0.3),
+                transmission: gray(0.3),
+                subsurface: gray(0.5),
+                ..Default::default()
+            }
+            .with_metallic_roughness(Rc::new(SolidColor::new(Color::new(0., 0.5, 0.3))), 1., 1.),
+        ));
+
+        for front_face in [true, false] {
+            assert_sample_matches_eval(principled.clone(), front_face);
+        }
+    }
+
+    #[test]
+    fn reads_gltf_metallic_roughness() {
+        let rec = HitRecord::default();
+        let packed = Rc::new(SolidColor::new(Color::new(0.9, 0.25, 1.)));
+        let p = Principled::new(PrincipledSettings::default().with_metallic_roughness(packed, 0.5, 1.)).params(&rec);
+
+        assert_eq!(p.roughness, 0.25);
+        assert_eq!(p.diffuse_weight, 0.5);
+        assert_eq!(p.f0, 0.5 * Color::new(0.04, 0.04, 0.04) + 0.5 * Color::new(0.8, 0.8, 0.8));
+    }
+}
@@ -0,0 +1,134 @@
+use fastrand::Rng;
+
+use crate::{figures::hittable::HitRecord, utility::{color::Color, ray::Ray, vec3::{Precision, Vec3}}};
+
+use super::{
+    fresnel,
+    material::{Lobe, Material, ScatteredRay},
+    microfacet::{Frame, Microfacet},
+};
+
+/// Frosted glass, reflecting and refracting off microfacets, after Walter et al.,
+/// "Microfacet Models for Refraction through Rough Surfaces", 2007.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoughDielectric {
+    refraction_index: Precision,
+    microfacet: Microfacet,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: Precision, microfacet: Microfacet) -> Self {
+        Self { refraction_index, microfacet }
+    }
+
+    /// Refraction index beyond the surface over the one on the side of the ray.
+    fn eta(&self, rec: &HitRecord) -> Precision {
+        if rec.front_face { self.refraction_index } else { 1. / self.refraction_index }
+    }
+
+    /// Microfacet normal between `wo` and `wi`, on the side of the normal, with the
+    /// Fresnel reflectance there. `None` if no microfacet joins them.
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3, eta: Precision) -> Option<(Vec3, Precision)> {
+        let reflected = wi.z() > 0.;
+        let h = if reflected { *wo + *wi } else { *wo + eta * *wi };
+        if h.near_zero() {
+            return None;
+        }
+
+        let h = if h.z() < 0. { -h.unit_vec() } else { h.unit_vec() };
+        let side = if reflected { 1. } else { -1. };
+        (wo.dot(&h) > 0. && side * wi.dot(&h) > 0.).then(|| (h, fresnel::dielectric(wo.dot(&h), eta)))
+    }
+}
+
+/// Direction `wo` refracts into through the microfacet `h`, with `eta` the refraction
+/// index beyond over the one on the side of `wo`. `None` under total internal reflection.
+fn refract(wo: &Vec3, h: &Vec3, eta: Precision) -> Option<Vec3> {
+    let cos_i = wo.dot(h);
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+
+    let cos_t = (1. - sin2_t).sqrt();
+    Some(-*wo / eta + (cos_i / eta - cos_t) * *h)
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatteredRay> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(&-ray.direction().unit_vec());
+        if wo.z() <= 0. {
+            return None;
+        }
+
+        // Reflect or refract with the chance the Fresnel term gives, which cancels it out
+        // of the weight.
+        let eta = self.eta(rec);
+        let h = self.microfacet.sample_visible(&wo, rng);
+        let reflectance = fresnel::dielectric(wo.dot(&h), eta);
+        let (wi, reflected) = match refract(&wo, &h, eta) {
+            Some(refracted) if rng.f32() >= reflectance => (refracted, false),
+            _ => ((-wo).reflect(h), true),
+        };
+        if wi.z() == 0. || (wi.z() > 0.) != reflected {
+            return None;
+        }
+
+        Some(ScatteredRay {
+            ray: Ray::new(rec.p, frame.to_world(&wi)),
+            attenuation: Color::new(1., 1., 1.) * (self.microfacet.g(&wo, &wi) / self.microfacet.g1(&wo)),
+            lobe: Lobe::Specular,
+        })
+    }
+
+    fn eval(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        let eta = self.eta(rec);
+        let Some((h, reflectance)) = (wo.z() > 0.).then(|| self.half_vector(&wo, &wi, eta)).flatten() else {
+            return Color::new(0., 0., 0.);
+        };
+
+        let dg = self.microfacet.d(&h) * self.microfacet.g(&wo, &wi);
+        let value = if wi.z() > 0. {
+            reflectance * dg / (4. * wo.z())
+        } else {
+            // Radiance is not scaled by the change of refraction index, as for `Dielectric`.
+            let denominator = wo.dot(&h) + eta * wi.dot(&h);
+            (1. - reflectance) * dg * eta * eta * wi.dot(&h).abs() * wo.dot(&h) / (wo.z() * denominator * denominator)
+        };
+        Color::new(value, value, value)
+    }
+
+    fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: &Vec3) -> Precision {
+        let (wo, wi) = Frame::local(ray, rec, direction);
+        let eta = self.eta(rec);
+        let Some((h, reflectance)) = (wo.z() > 0.).then(|| self.half_vector(&wo, &wi, eta)).flatten() else {
+            return 0.;
+        };
+
+        let visible = self.microfacet.visible_density(&wo, &h);
+        if wi.z() > 0. {
+            reflectance * visible / (4. * wo.dot(&h))
+        } else {
+            let denominator = wo.dot(&h) + eta * wi.dot(&h);
+            (1. - reflectance) * visible * eta * eta * wi.dot(&h).abs() / (denominator * denominator)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::materials::{material::tests::assert_sample_matches_eval, microfacet::Distribution};
+
+    use super::*;
+
+    #[test]
+    fn scattering_agrees_with_the_bsdf() {
+        for (distribution, front_face) in [(Distribution::Ggx, true), (Distribution::Beckmann, true), (Distribution::Ggx, false)] {
+            assert_sample_matches_eval(Rc::new(RoughDielectric::new(1.5, Microfacet::new(distribution, 0.5))), front_face);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::utility::{color::Color, vec3::Precision};
+
+/// Settings for adaptive sampling. `ImageSettings::samples_per_pixel` is the upper bound
+/// on the number of samples taken for a pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSettings {
+    /// Samples always taken before a pixel is allowed to stop.
+    pub min_samples: i32,
+    /// A pixel stops once the relative standard error of its luminance drops below this.
+    pub noise_threshold: Precision,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        let min_samples = 16;
+        let noise_threshold = 0.01;
+
+        Self {
+            min_samples,
+            noise_threshold,
+        }
+    }
+}
+
+/// Running estimate of a pixel's value and of the noise in it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PixelEstimate {
+    sum: Color,
+    count: i32,
+    /// Samples taken but left out, see `skip`.
+    skipped: i32,
+    // Welford's running mean and squared deviations of the sample luminance.
+    mean: Precision,
+    m2: Precision,
+}
+
+/// Pixels darker than this are judged against this luminance instead, so that the
+/// relative error of near black pixels does not blow up.
+const MIN_LUMINANCE: Precision = 0.01;
+
+impl PixelEstimate {
+    pub fn add(&mut self, sample: Color) {
+        self.sum += sample;
+        self.count += 1;
+
+        let luminance = sample.luminance();
+        let delta = luminance - self.mean;
+        self.mean += delta / self.count as Precision;
+        self.m2 += delta * (luminance - self.mean);
+    }
+
+    /// Counts a sample as taken without adding it to the estimate, for samples that came
+    /// out invalid.
+    pub fn skip(&mut self) {
+        self.skipped += 1;
+    }
+
+    /// Running sums, in the order `sum, count, skipped, mean, m2`, to save and restore
+    /// estimates.
+    pub fn raw_parts(&self) -> (Color, i32, i32, Precision, Precision) {
+        (self.sum, self.count, self.skipped, self.mean, self.m2)
+    }
+
+    pub fn from_raw_parts(sum: Color, count: i32, skipped: i32, mean: Precision, m2: Precision) -> Self {
+        Self { sum, count, skipped, mean, m2 }
+    }
+
+    /// Samples taken, including skipped ones.
+    pub fn count(&self) -> i32 {
+        self.count + self.skipped
+    }
+
+    pub fn sum(&self) -> Color {
+        self.sum
+    }
+
+    pub fn mean(&self) -> Color {
+        if self.count == 0 {
+            return Color::default();
+        }
+
+        self.sum / self.count as Precision
+    }
+
+    /// Standard error of the mean luminance, relative to the mean luminance.
+    pub fn relative_error(&self) -> Precision {
+        if self.count < 2 {
+            return Precision::INFINITY;
+        }
+
+        let variance = self.m2 / (self.count - 1) as Precision;
+        let std_error = (variance / self.count as Precision).sqrt();
+
+        std_error / self.mean.max(MIN_LUMINANCE)
+    }
+
+    pub fn converged(&self, settings: &AdaptiveSettings) -> bool {
+        self.count >= settings.min_samples && self.relative_error() < settings.noise_threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_pixel_converges() {
+        let settings = AdaptiveSettings::default();
+        let mut estimate = PixelEstimate::default();
+
+        for _ in 0..settings.min_samples {
+            assert!(!estimate.converged(&settings));
+            estimate.add(Color::new(0.5, 0.25, 1.0));
+        }
+
+        assert!(estimate.converged(&settings));
+        assert_eq!(estimate.mean(), Color::new(0.5, 0.25, 1.0));
+    }
+
+    #[test]
+    fn noisy_pixel_keeps_sampling() {
+        let settings = AdaptiveSettings::default();
+        let mut estimate = PixelEstimate::default();
+
+        for i in 0..settings.min_samples {
+            let v = if i % 2 == 0 { 0. } else { 1. };
+            estimate.add(Color::new(v, v, v));
+        }
+
+        assert!(!estimate.converged(&settings));
+    }
+}
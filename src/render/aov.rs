@@ -0,0 +1,504 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    figures::hittable::HitRecord,
+    image_formats::{
+        exr::{ExrChannel, EXR},
+        ppm::PPM,
+    },
+    materials::material::{Lobe, Material},
+    utility::{
+        color::Color,
+        ray::Ray,
+        utils::{fnv1a, mix_seed},
+        vec3::{Point3, Precision, Vec3},
+    },
+};
+
+use super::{
+    progressive::{invalid_data, read_bytes, read_color, read_precision, write_color, write_precision},
+    tonemap::DisplayTransform,
+};
+
+/// Arbitrary output variable, an extra image rendered alongside the beauty image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aov {
+    /// Distance from the camera to the first surface hit.
+    Depth,
+    /// World space shading normal of the first surface hit.
+    Normal,
+    /// World space position of the first surface hit.
+    Position,
+    Albedo,
+    /// Material of the first surface hit, numbered in the order the materials are met
+    /// in the image, starting at 1. Background pixels are 0.
+    MaterialId,
+    /// Index in the world of the object hit first plus one. Background pixels are 0.
+    ObjectId,
+    /// Light reaching the camera after one diffuse bounce.
+    DirectDiffuse,
+    /// Light reaching the camera after a diffuse first bounce and more bounces.
+    IndirectDiffuse,
+    /// Light reaching the camera after one specular bounce.
+    DirectSpecular,
+    /// Light reaching the camera after a specular first bounce and more bounces.
+    IndirectSpecular,
+    /// Light seen directly, from emitting surfaces or the background.
+    Emission,
+    /// Coverage of the pixel by surfaces.
+    Alpha,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 12] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Position,
+        Aov::Albedo,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::DirectDiffuse,
+        Aov::IndirectDiffuse,
+        Aov::DirectSpecular,
+        Aov::IndirectSpecular,
+        Aov::Emission,
+        Aov::Alpha,
+    ];
+
+    /// Name of the pass, used for the file names and EXR layers.
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Albedo => "albedo",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::DirectDiffuse => "direct_diffuse",
+            Aov::IndirectDiffuse => "indirect_diffuse",
+            Aov::DirectSpecular => "direct_specular",
+            Aov::IndirectSpecular => "indirect_specular",
+            Aov::Emission => "emission",
+            Aov::Alpha => "alpha",
+        }
+    }
+
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::MaterialId | Aov::ObjectId => &["id"],
+            Aov::Alpha => &["A"],
+            Aov::Albedo
+            | Aov::DirectDiffuse
+            | Aov::IndirectDiffuse
+            | Aov::DirectSpecular
+            | Aov::IndirectSpecular
+            | Aov::Emission => &["R", "G", "B"],
+        }
+    }
+}
+
+/// Where `Camera::render` writes the passes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AovOutput {
+    /// One PPM image per pass, named after the pass, in the given directory.
+    Images(PathBuf),
+    /// A single EXR file with one layer per pass.
+    Exr(PathBuf),
+}
+
+/// Passes to render besides the beauty image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AovSettings {
+    pub aovs: Vec<Aov>,
+    /// Where `Camera::render` writes the passes. Either way they are part of the
+    /// `RenderOutput`.
+    pub output: Option<AovOutput>,
+}
+
+impl Default for AovSettings {
+    fn default() -> Self {
+        let aovs = Aov::ALL.to_vec();
+        let output = None;
+
+        Self { aovs, output }
+    }
+}
+
+/// What the camera ray of a sample hit first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceSample {
+    pub depth: Precision,
+    pub normal: Vec3,
+    pub position: Point3,
+    pub albedo: Color,
+    /// Identifies the material, see `material_key`.
+    pub material: u64,
+    pub object_id: u32,
+}
+
+impl SurfaceSample {
+    /// The surface `ray` hit, described by `rec`.
+    pub fn new(ray: &Ray, rec: &HitRecord) -> Self {
+        Self {
+            depth: rec.t * ray.direction().len(),
+            normal: rec.normal,
+            position: rec.p,
+            albedo: rec.material.albedo(rec),
+            material: material_key(rec.material.as_ref()),
+            object_id: rec.object_id,
+        }
+    }
+}
+
+/// Everything a single sample contributes to the passes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AovSample {
+    pub surface: Option<SurfaceSample>,
+    pub emission: Color,
+    pub direct_diffuse: Color,
+    pub indirect_diffuse: Color,
+    pub direct_specular: Color,
+    pub indirect_specular: Color,
+}
+
+impl AovSample {
+    /// Files light reaching the camera from a vertex `bounce` bounces down a path whose
+    /// first bounce scattered off `first_lobe`.
+    pub fn add_light(&mut self, bounce: i32, first_lobe: Option<Lobe>, light: Color) {
+        let pass = match (bounce, first_lobe) {
+            (_, None) => &mut self.emission,
+            (1, Some(Lobe::Diffuse)) => &mut self.direct_diffuse,
+            (1, Some(Lobe::Specular)) => &mut self.direct_specular,
+            (_, Some(Lobe::Diffuse)) => &mut self.indirect_diffuse,
+            (_, Some(Lobe::Specular)) => &mut self.indirect_specular,
+        };
+        *pass += light;
+    }
+
+    /// Scales the light in every pass, for camera rays carrying a weight.
+    pub fn scale_light(&mut self, weight: Color) {
+        self.emission *= weight;
+        self.direct_diffuse *= weight;
+        self.indirect_diffuse *= weight;
+        self.direct_specular *= weight;
+        self.indirect_specular *= weight;
+    }
+}
+
+/// Accumulated samples of one pixel. Surface passes average over the samples that hit a
+/// surface, light passes over all the samples. Identifiers come from the first sample
+/// that hit a surface, as they cannot be averaged.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AovPixel {
+    samples: u32,
+    hits: u32,
+    depth: Precision,
+    normal: Vec3,
+    position: Point3,
+    albedo: Color,
+    material: Option<u64>,
+    object_id: Option<u32>,
+    light: AovSample,
+}
+
+impl AovPixel {
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.samples.to_le_bytes())?;
+        out.write_all(&self.hits.to_le_bytes())?;
+        write_precision(out, self.depth)?;
+        write_color(out, self.normal)?;
+        write_color(out, self.position)?;
+        write_color(out, self.albedo)?;
+        out.write_all(&[self.material.is_some() as u8])?;
+        out.write_all(&self.material.unwrap_or(0).to_le_bytes())?;
+        out.write_all(&[self.object_id.is_some() as u8])?;
+        out.write_all(&self.object_id.unwrap_or(0).to_le_bytes())?;
+        for light in [
+            self.light.emission,
+            self.light.direct_diffuse,
+            self.light.indirect_diffuse,
+            self.light.direct_specular,
+            self.light.indirect_specular,
+        ] {
+            write_color(out, light)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_from(input: &mut impl Read) -> io::Result<Self> {
+        let samples = u32::from_le_bytes(read_bytes(input)?);
+        let hits = u32::from_le_bytes(read_bytes(input)?);
+        let depth = read_precision(input)?;
+        let normal = read_color(input)?;
+        let position = read_color(input)?;
+        let albedo = read_color(input)?;
+        let has_material = read_flag(input)?;
+        let material = has_material.then_some(u64::from_le_bytes(read_bytes(input)?));
+        let has_object_id = read_flag(input)?;
+        let object_id = has_object_id.then_some(u32::from_le_bytes(read_bytes(input)?));
+        let light = AovSample {
+            surface: None,
+            emission: read_color(input)?,
+            direct_diffuse: read_color(input)?,
+            indirect_diffuse: read_color(input)?,
+            direct_specular: read_color(input)?,
+            indirect_specular: read_color(input)?,
+        };
+
+        Ok(Self {
+            samples,
+            hits,
+            depth,
+            normal,
+            position,
+            albedo,
+            material,
+            object_id,
+            light,
+        })
+    }
+
+    /// Average albedo of the surfaces seen through the pixel, black if there are none.
+    pub fn albedo(&self) -> Color {
+        self.albedo / self.hits.max(1) as Precision
+    }
+
+    /// Average normal of the surfaces seen through the pixel, zero if there are none.
+    pub fn normal(&self) -> Vec3 {
+        let normal = self.normal / self.hits.max(1) as Precision;
+        if normal.near_zero() {
+            normal
+        } else {
+            normal.unit_vec()
+        }
+    }
+
+    /// Values of the pixel for `aov`, one per channel. `None` stands for a pixel outside
+    /// the rendered region.
+    pub fn values(pixel: Option<&AovPixel>, aov: Aov, materials: &mut MaterialIds) -> Vec<Precision> {
+        let Some(pixel) = pixel else {
+            return vec![0.; aov.channels().len()];
+        };
+
+        let hits = pixel.hits.max(1) as Precision;
+        let samples = pixel.samples.max(1) as Precision;
+        let color = |c: Color| vec![c.x(), c.y(), c.z()];
+        match aov {
+            Aov::Depth => vec![pixel.depth / hits],
+            Aov::Normal => color(pixel.normal()),
+            Aov::Position => color(pixel.position / hits),
+            Aov::Albedo => color(pixel.albedo()),
+            Aov::MaterialId => vec![pixel.material.map_or(0, |material| materials.id(material)) as Precision],
+            Aov::ObjectId => vec![pixel.object_id.map_or(0, |id| id + 1) as Precision],
+            Aov::DirectDiffuse => color(pixel.light.direct_diffuse / samples),
+            Aov::IndirectDiffuse => color(pixel.light.indirect_diffuse / samples),
+            Aov::DirectSpecular => color(pixel.light.direct_specular / samples),
+            Aov::IndirectSpecular => color(pixel.light.indirect_specular / samples),
+            Aov::Emission => color(pixel.light.emission / samples),
+            Aov::Alpha => vec![pixel.hits as Precision / samples],
+        }
+    }
+}
+
+/// Per pixel passes of one view, box filtered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AovBuffer {
+    cols: usize,
+    pixels: Vec<AovPixel>,
+}
+
+impl AovBuffer {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            pixels: vec![AovPixel::default(); cols * rows],
+        }
+    }
+
+    pub fn add_sample(&mut self, row: usize, col: usize, sample: &AovSample) {
+        let pixel = &mut self.pixels[row * self.cols + col];
+        pixel.samples += 1;
+
+        if let Some(surface) = sample.surface {
+            pixel.hits += 1;
+            pixel.depth += surface.depth;
+            pixel.normal += surface.normal;
+            pixel.position += surface.position;
+            pixel.albedo += surface.albedo;
+            pixel.material.get_or_insert(surface.material);
+            pixel.object_id.get_or_insert(surface.object_id);
+        }
+
+        pixel.light.emission += sample.emission;
+        pixel.light.direct_diffuse += sample.direct_diffuse;
+        pixel.light.indirect_diffuse += sample.indirect_diffuse;
+        pixel.light.direct_specular += sample.direct_specular;
+        pixel.light.indirect_specular += sample.indirect_specular;
+    }
+
+    pub fn pixel(&self, row: usize, col: usize) -> &AovPixel {
+        &self.pixels[row * self.cols + col]
+    }
+
+    /// Writes the accumulated passes, for checkpoints.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        self.pixels.iter().try_for_each(|pixel| pixel.write_to(out))
+    }
+
+    /// Reads passes written by `AovBuffer::write_to`.
+    pub fn read_from(input: &mut impl Read, cols: usize, rows: usize) -> io::Result<Self> {
+        let pixels = (0..cols * rows).map(|_| AovPixel::read_from(input)).collect::<io::Result<_>>()?;
+
+        Ok(Self { cols, pixels })
+    }
+}
+
+fn read_flag(input: &mut impl Read) -> io::Result<bool> {
+    match read_bytes::<1>(input)? {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        _ => Err(invalid_data("invalid flag in checkpoint")),
+    }
+}
+
+/// Identifies `material` by its parameters, so that it is the same from run to run and
+/// from build to build, unlike its address.
+pub fn material_key(material: &dyn Material) -> u64 {
+    fnv1a(format!("{material:?}").as_bytes())
+}
+
+/// Numbers materials in the order they are first met.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialIds {
+    ids: HashMap<u64, u32>,
+}
+
+impl MaterialIds {
+    pub fn id(&mut self, material: u64) -> u32 {
+        let next = self.ids.len() as u32 + 1;
+        *self.ids.entry(material).or_insert(next)
+    }
+}
+
+/// A rendered pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AovImage {
+    pub aov: Aov,
+    pub cols: usize,
+    pub rows: usize,
+    /// Values of the channels of every pixel, interleaved, in row major order.
+    pub values: Vec<Precision>,
+}
+
+impl AovImage {
+    /// Values of `channel` for every pixel.
+    pub fn channel(&self, channel: usize) -> Vec<Precision> {
+        let channels = self.aov.channels().len();
+        self.values.iter().skip(channel).step_by(channels).copied().collect()
+    }
+
+    /// Viewable image of the pass. Light passes go through `display` like the beauty
+    /// image, depth is scaled so the farthest surface is white, normals are mapped from
+    /// `[-1, 1]` to `[0, 1]` and identifiers get random colors.
+    pub fn to_ppm(&self, display: &DisplayTransform) -> PPM {
+        let channels = self.aov.channels().len();
+        let pixels = self.values.chunks(channels);
+        let max_depth = self.values.iter().copied().fold(0., Precision::max).max(Precision::EPSILON);
+
+        let values = pixels
+            .map(|v| match self.aov {
+                Aov::Depth => Color::new(1., 1., 1.) * (v[0] / max_depth),
+                Aov::Alpha => Color::new(v[0], v[0], v[0]),
+                Aov::Normal => 0.5 * (Color::new(v[0], v[1], v[2]) + Color::new(1., 1., 1.)),
+                Aov::Position => Color::new(v[0].fract().abs(), v[1].fract().abs(), v[2].fract().abs()),
+                Aov::Albedo => Color::new(v[0], v[1], v[2]),
+                Aov::MaterialId | Aov::ObjectId => id_color(v[0] as u64),
+                _ => display.apply(Color::new(v[0], v[1], v[2])),
+            })
+            .collect();
+
+        PPM::new(self.cols, self.rows, 255, values)
+    }
+}
+
+/// Stable, well spread color for an identifier, black for 0.
+pub fn id_color(id: u64) -> Color {
+    if id == 0 {
+        return Color::new(0., 0., 0.);
+    }
+
+    let hash = mix_seed(0, id);
+    let channel = |shift: u32| ((hash >> shift) & 0xff) as Precision / 255.;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+/// Writes the passes as `output` asks for.
+pub fn write_aovs(aovs: &[AovImage], output: &AovOutput, display: &DisplayTransform) -> io::Result<()> {
+    match output {
+        AovOutput::Images(directory) => {
+            fs::create_dir_all(directory)?;
+            for aov in aovs {
+                aov.to_ppm(display).save(directory.join(format!("{}.ppm", aov.aov.name())))?;
+            }
+            Ok(())
+        }
+        AovOutput::Exr(path) => {
+            let Some(first) = aovs.first() else {
+                return Ok(());
+            };
+
+            let channels = aovs
+                .iter()
+                .flat_map(|aov| {
+                    aov.aov.channels().iter().enumerate().map(|(i, channel)| ExrChannel {
+                        name: format!("{}.{channel}", aov.aov.name()),
+                        values: aov.channel(i),
+                    })
+                })
+                .collect();
+
+            EXR::new(first.cols, first.rows, channels).save(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::metal::Metal;
+
+    use super::*;
+
+    #[test]
+    fn light_is_filed_by_bounce_and_lobe() {
+        let light = Color::new(1., 2., 3.);
+        let mut sample = AovSample::default();
+        sample.add_light(0, None, light);
+        sample.add_light(1, Some(Lobe::Diffuse), light);
+        sample.add_light(2, Some(Lobe::Diffuse), light);
+        sample.add_light(5, Some(Lobe::Diffuse), light);
+        sample.add_light(1, Some(Lobe::Specular), light);
+
+        assert_eq!(sample.emission, light);
+        assert_eq!(sample.direct_diffuse, light);
+        assert_eq!(sample.indirect_diffuse, 2. * light);
+        assert_eq!(sample.direct_specular, light);
+        assert_eq!(sample.indirect_specular, Color::default());
+    }
+    #[test]
+    fn material_keys_are_fixed() {
+        // A published FNV-1a value, so that keys saved in checkpoints stay valid.
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+
+        let metal = |fuzz| material_key(&Metal::new(Color::new(0.5, 0.5, 0.5), fuzz));
+        assert_eq!(metal(0.1), metal(0.1));
+        assert_ne!(metal(0.), metal(0.1));
+    }
+}
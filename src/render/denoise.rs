@@ -0,0 +1,149 @@
+use crate::utility::{
+    color::Color,
+    vec3::{Precision, Vec3},
+};
+
+/// Settings of the edge-avoiding à-trous wavelet denoiser, after Dammertz et al. 2010.
+/// Edges are found in the albedo and normal passes, which are rendered automatically
+/// when denoising.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DenoiseSettings {
+    /// How much noise to remove, 0 leaves the image as is. Higher values blur across
+    /// larger differences in color.
+    pub strength: Precision,
+    /// Number of filter passes, each one doubling the footprint of the filter.
+    pub iterations: u32,
+    /// How different normals can be and still be blurred together.
+    pub sigma_normal: Precision,
+    /// How different albedos can be and still be blurred together.
+    pub sigma_albedo: Precision,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        let strength = 1.;
+        let iterations = 5;
+        let sigma_normal = 0.3;
+        let sigma_albedo = 0.1;
+
+        Self {
+            strength,
+            iterations,
+            sigma_normal,
+            sigma_albedo,
+        }
+    }
+}
+
+/// B3 spline kernel the à-trous filter spreads out.
+const KERNEL: [Precision; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+
+/// Color difference that stops the first pass at `strength` 1.
+const SIGMA_COLOR: Precision = 0.25;
+
+/// Denoises a linear image of `cols` by `rows` pixels in row major order, guided by the
+/// albedo and normal of the surfaces seen in every pixel.
+pub fn denoise(
+    color: &[Color],
+    albedo: &[Color],
+    normal: &[Vec3],
+    cols: usize,
+    rows: usize,
+    settings: &DenoiseSettings,
+) -> Vec<Color> {
+    assert!(color.len() == cols * rows && albedo.len() == cols * rows && normal.len() == cols * rows);
+    if settings.strength <= 0. {
+        return color.to_vec();
+    }
+
+    // Filter the lighting without the surface colors, so that texture detail survives.
+    let demodulate = |a: Color| {
+        let safe = |v: Precision| if v > 0.01 { v } else { 1. };
+        Color::new(safe(a.x()), safe(a.y()), safe(a.z()))
+    };
+    let mut irradiance: Vec<Color> = color.iter().zip(albedo).map(|(&c, &a)| c / demodulate(a)).collect();
+
+    let mut sigma_color = settings.strength * SIGMA_COLOR;
+    for iteration in 0..settings.iterations {
+        let step = 1 << iteration;
+        let mut filtered = Vec::with_capacity(irradiance.len());
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let p = row * cols + col;
+                let (c_p, n_p, a_p) = (compress(irradiance[p]), normal[p], albedo[p]);
+
+                let mut sum = Color::default();
+                let mut weights = 0.;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let y = row as isize + (j as isize - 2) * step;
+                    if y < 0 || y >= rows as isize {
+                        continue;
+                    }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let x = col as isize + (i as isize - 2) * step;
+                        if x < 0 || x >= cols as isize {
+                            continue;
+                        }
+
+                        let q = y as usize * cols + x as usize;
+                        let w = kx
+                            * ky
+                            * edge_stop((compress(irradiance[q]) - c_p).len_square(), sigma_color)
+                            * edge_stop((normal[q] - n_p).len_square(), settings.sigma_normal)
+                            * edge_stop((albedo[q] - a_p).len_square(), settings.sigma_albedo);
+                        sum += w * irradiance[q];
+                        weights += w;
+                    }
+                }
+
+                // The center pixel always has a positive weight.
+                filtered.push(sum / weights);
+            }
+        }
+
+        irradiance = filtered;
+        sigma_color /= 2.;
+    }
+
+    irradiance.iter().zip(albedo).map(|(&c, &a)| c * demodulate(a)).collect()
+}
+
+/// Maps HDR colors into `[0, 1)` so that bright pixels do not stop the filter everywhere.
+fn compress(c: Color) -> Color {
+    Color::new(c.x() / (1. + c.x()), c.y() / (1. + c.y()), c.z() / (1. + c.z()))
+}
+
+fn edge_stop(distance_square: Precision, sigma: Precision) -> Precision {
+    (-distance_square / (sigma * sigma).max(Precision::EPSILON)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_edges_and_smooths_noise() {
+        let (cols, rows) = (8, 4);
+        // Left half faces up and is noisy, right half faces sideways and is flat.
+        let normal: Vec<Vec3> = (0..cols * rows)
+            .map(|i| if i % cols < 4 { Vec3::new(0., 1., 0.) } else { Vec3::new(1., 0., 0.) })
+            .collect();
+        let color: Vec<Color> = (0..cols * rows)
+            .map(|i| match (i % cols < 4, i % 2 == 0) {
+                (true, true) => Color::new(0.6, 0.6, 0.6),
+                (true, false) => Color::new(0.4, 0.4, 0.4),
+                (false, _) => Color::new(0.1, 0.1, 0.1),
+            })
+            .collect();
+        let albedo = vec![Color::new(1., 1., 1.); cols * rows];
+
+        let denoised = denoise(&color, &albedo, &normal, cols, rows, &DenoiseSettings::default());
+
+        // The noise is ±0.1 to begin with.
+        for (i, c) in denoised.iter().enumerate() {
+            let expected = if i % cols < 4 { 0.5 } else { 0.1 };
+            assert!((c.x() - expected).abs() < 0.04, "pixel {i} is {c}");
+        }
+    }
+}
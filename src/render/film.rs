@@ -0,0 +1,174 @@
+use crate::{
+    image_formats::ppm::PPM,
+    utility::{color::Color, vec3::Precision},
+};
+
+use super::filter::Filter;
+
+/// Accumulates filtered samples for every pixel of an image.
+///
+/// Sample positions are in continuous raster coordinates: pixel `(row, col)` covers
+/// `[col, col + 1) x [row, row + 1)` and its center is at `(col + 0.5, row + 0.5)`.
+///
+/// Light carried straight to the camera by light paths lands anywhere on the film, and
+/// is kept apart from the samples of each pixel, see `Film::add_light`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Film {
+    cols: usize,
+    rows: usize,
+    filter: Filter,
+    sums: Vec<Color>,
+    weights: Vec<Precision>,
+    light: Vec<Color>,
+    light_paths: u64,
+}
+
+impl Film {
+    pub fn new(cols: usize, rows: usize, filter: Filter) -> Self {
+        Self {
+            cols,
+            rows,
+            filter,
+            sums: vec![Color::default(); cols * rows],
+            weights: vec![0.; cols * rows],
+            light: vec![Color::default(); cols * rows],
+            light_paths: 0,
+        }
+    }
+
+    /// Film with previously accumulated weighted sums and weights, see `Film::accumulated`.
+    pub fn from_accumulated(cols: usize, rows: usize, filter: Filter, sums: Vec<Color>, weights: Vec<Precision>) -> Self {
+        assert!(sums.len() == cols * rows && weights.len() == cols * rows);
+
+        Self {
+            cols,
+            rows,
+            filter,
+            sums,
+            weights,
+            light: vec![Color::default(); cols * rows],
+            light_paths: 0,
+        }
+    }
+
+    /// Weighted sums of the samples and sums of the weights for every pixel.
+    pub fn accumulated(&self) -> (&[Color], &[Precision]) {
+        (&self.sums, &self.weights)
+    }
+
+    /// Light added with `Film::add_light` for every pixel, and the number of light paths
+    /// traced.
+    pub fn light(&self) -> (&[Color], u64) {
+        (&self.light, self.light_paths)
+    }
+
+    /// Replaces the light, see `Film::light`.
+    pub fn set_light(&mut self, light: Vec<Color>, light_paths: u64) {
+        assert!(light.len() == self.cols * self.rows);
+        self.light = light;
+        self.light_paths = light_paths;
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Splats a sample taken at raster position `x`, `y` into every pixel within the
+    /// filter radius.
+    pub fn add_sample(&mut self, x: Precision, y: Precision, color: Color) {
+        let radius = self.filter.radius();
+
+        // Range of pixels whose centers lie within the filter radius.
+        let col_min = (x - radius - 0.5).ceil().max(0.) as usize;
+        let col_max = ((x + radius - 0.5).floor() as i64).min(self.cols as i64 - 1);
+        let row_min = (y - radius - 0.5).ceil().max(0.) as usize;
+        let row_max = ((y + radius - 0.5).floor() as i64).min(self.rows as i64 - 1);
+
+        for row in row_min as i64..=row_max {
+            for col in col_min as i64..=col_max {
+                let dx = x - (col as Precision + 0.5);
+                let dy = y - (row as Precision + 0.5);
+                let weight = self.filter.evaluate(dx, dy);
+                if weight == 0. {
+                    continue;
+                }
+
+                let index = row as usize * self.cols + col as usize;
+                self.sums[index] += weight * color;
+                self.weights[index] += weight;
+            }
+        }
+    }
+
+    /// Adds light that a light path carried to raster position `x`, `y`. It goes to the
+    /// pixel it lands in, unfiltered.
+    pub fn add_light(&mut self, x: Precision, y: Precision, color: Color) {
+        if x < 0. || y < 0. || x >= self.cols as Precision || y >= self.rows as Precision {
+            return;
+        }
+
+        self.light[y as usize * self.cols + x as usize] += color;
+    }
+
+    /// Counts light paths traced, whether they reached the film or not. Light is averaged
+    /// over all of them.
+    pub fn add_light_paths(&mut self, paths: u64) {
+        self.light_paths += paths;
+    }
+
+    /// Filtered value of a pixel. Filters with negative lobes can push the value below
+    /// zero around sharp edges, so it is clamped to black.
+    pub fn pixel(&self, row: usize, col: usize) -> Color {
+        let index = row * self.cols + col;
+        let weight = self.weights[index];
+        let mut c = if weight > 0. { self.sums[index] / weight } else { Color::default() };
+        if self.light_paths > 0 {
+            c += self.light[index] / self.light_paths as Precision;
+        }
+
+        Color::new(c.x().max(0.), c.y().max(0.), c.z().max(0.))
+    }
+
+    pub fn to_ppm(&self) -> PPM {
+        let values = (0..self.rows)
+            .flat_map(|row| (0..self.cols).map(move |col| (row, col)))
+            .map(|(row, col)| self.pixel(row, col))
+            .collect();
+
+        PPM::new(self.cols, self.rows, 255, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splats_into_neighbouring_pixels() {
+        let red = Color::new(1., 0., 0.);
+        let blue = Color::new(0., 0., 1.);
+
+        // A box filter the size of a pixel keeps samples in their own pixel.
+        let mut film = Film::new(3, 3, Filter::default());
+        film.add_sample(1.2, 1.7, red);
+        let (_, weights) = film.accumulated();
+        assert_eq!(weights, &[0., 0., 0., 0., 1., 0., 0., 0., 0.]);
+
+        // A wider tent reaches the neighbours, less so the diagonal ones.
+        let mut film = Film::new(3, 3, Filter::Tent { radius: 1.5 });
+        film.add_sample(1.5, 1.5, red);
+        let (_, weights) = film.accumulated();
+        assert_eq!(weights, &[0.25, 0.75, 0.25, 0.75, 2.25, 0.75, 0.25, 0.75, 0.25]);
+        assert_eq!(film.pixel(0, 0), red);
+
+        // A sample in a corner pixel blends into the center one, and off the image.
+        film.add_sample(0.5, 0.5, blue);
+        assert_eq!(film.pixel(1, 1), (2.25 * red + 0.25 * blue) / 2.5);
+        assert_eq!(film.pixel(0, 0), (0.25 * red + 2.25 * blue) / 2.5);
+        assert_eq!(film.pixel(2, 2), red);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::utility::{utils::pi32, vec3::Precision};
+
+/// Pixel reconstruction filter. Every sample is splatted into all the pixels whose center
+/// lies within `radius` pixels of it, weighted by the filter.
+///
+/// Filters are separable, the weight of an offset `(x, y)` is `f(x) * f(y)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box { radius: Precision },
+    Tent { radius: Precision },
+    Gaussian { radius: Precision, sigma: Precision },
+    /// Mitchell–Netravali cubic. `b = c = 1/3` is the recommended compromise between
+    /// blurring and ringing.
+    Mitchell { radius: Precision, b: Precision, c: Precision },
+    /// Sinc windowed by a sinc stretched over the filter radius.
+    Lanczos { radius: Precision },
+}
+
+impl Filter {
+    pub fn gaussian(radius: Precision) -> Self {
+        Filter::Gaussian { radius, sigma: radius / 3. }
+    }
+
+    pub fn mitchell(radius: Precision) -> Self {
+        Filter::Mitchell { radius, b: 1. / 3., c: 1. / 3. }
+    }
+
+    pub fn radius(&self) -> Precision {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    /// Weight of a sample `x`, `y` pixels away from a pixel center.
+    pub fn evaluate(&self, x: Precision, y: Precision) -> Precision {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: Precision) -> Precision {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.;
+        }
+
+        match *self {
+            Filter::Box { .. } => 1.,
+            Filter::Tent { radius } => radius - x,
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |x: Precision| (-x * x / (2. * sigma * sigma)).exp();
+                // Shift down so that the filter reaches zero at its radius.
+                (gaussian(x) - gaussian(radius)).max(0.)
+            }
+            Filter::Mitchell { radius, b, c } => mitchell_1d(2. * x / radius, b, c),
+            Filter::Lanczos { radius } => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+/// Mitchell–Netravali cubic for `x` in `[0, 2]`.
+fn mitchell_1d(x: Precision, b: Precision, c: Precision) -> Precision {
+    let x2 = x * x;
+    let x3 = x2 * x;
+
+    let value = if x < 1. {
+        (12. - 9. * b - 6. * c) * x3 + (-18. + 12. * b + 6. * c) * x2 + (6. - 2. * b)
+    } else {
+        (-b - 6. * c) * x3 + (6. * b + 30. * c) * x2 + (-12. * b - 48. * c) * x + (8. * b + 24. * c)
+    };
+
+    value / 6.
+}
+
+fn sinc(x: Precision) -> Precision {
+    if x.abs() < 1e-5 {
+        return 1.;
+    }
+
+    let px = pi32 * x;
+    px.sin() / px
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_vanish_outside_radius() {
+        let filters = [
+            Filter::Box { radius: 1. },
+            Filter::Tent { radius: 1. },
+            Filter::gaussian(1.5),
+            Filter::mitchell(2.),
+            Filter::Lanczos { radius: 3. },
+        ];
+
+        for filter in filters {
+            let r = filter.radius();
+            assert!(filter.evaluate(0., 0.) > 0., "{filter:?}");
+            assert!(filter.evaluate(r * 0.99, 0.) <= filter.evaluate(0., 0.), "{filter:?}");
+            assert_eq!(filter.evaluate(r + 0.01, 0.), 0., "{filter:?}");
+            assert_eq!(filter.evaluate(0., -r - 0.01), 0., "{filter:?}");
+        }
+    }
+}
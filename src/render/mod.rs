@@ -0,0 +1,13 @@
+pub mod adaptive;
+pub mod aov;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod output;
+pub mod progress;
+pub mod progressive;
+pub mod region;
+pub mod roulette;
+pub mod stats;
+pub mod tonemap;
+pub mod trace;
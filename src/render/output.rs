@@ -0,0 +1,38 @@
+use crate::{
+    image_formats::ppm::PPM,
+    utility::{color::Color, vec3::Precision},
+};
+
+use super::{aov::AovImage, stats::RenderStats};
+
+/// Everything a render produces besides writing the image out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOutput {
+    pub image: PPM,
+    /// Number of samples taken for every pixel, in row major order.
+    pub sample_counts: Vec<i32>,
+    /// Whether the render was cancelled, leaving the image partially rendered.
+    pub cancelled: bool,
+    /// Its work counters are only kept when `ImageSettings::stats` is set.
+    pub stats: RenderStats,
+    /// Render passes asked for by `ImageSettings::aovs`, in the same order.
+    pub aovs: Vec<AovImage>,
+}
+
+impl RenderOutput {
+    /// Grayscale image of the samples taken per pixel, scaled so that the most sampled
+    /// pixel is white.
+    pub fn sample_map(&self) -> PPM {
+        let max = self.sample_counts.iter().copied().max().unwrap_or(0).max(1) as Precision;
+        let values = self
+            .sample_counts
+            .iter()
+            .map(|&count| {
+                let v = count as Precision / max;
+                Color::new(v, v, v)
+            })
+            .collect();
+
+        PPM::new(self.image.cols(), self.image.rows(), 255, values)
+    }
+}
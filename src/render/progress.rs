@@ -0,0 +1,188 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::utility::vec3::Precision;
+
+/// Snapshot of how far along a render is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Fraction of the sample budget taken so far, in `[0, 1]`. Renders with adaptive
+    /// sampling can finish before reaching 1.
+    pub fraction: Precision,
+    pub elapsed: Duration,
+    /// Estimated time left, once there is enough progress to tell.
+    pub eta: Option<Duration>,
+    pub rays_per_second: Precision,
+    /// Progressive rendering pass being rendered, starting at 1.
+    pub pass: u32,
+}
+
+/// Receives progress updates from a running render.
+pub trait ProgressObserver {
+    /// Called regularly while rendering, possibly many times a second.
+    fn on_progress(&mut self, progress: &Progress);
+
+    /// Called once when the render stops, whether it finished or was cancelled.
+    fn on_finished(&mut self, _progress: &Progress, _cancelled: bool) {}
+}
+
+impl<F: FnMut(&Progress)> ProgressObserver for F {
+    fn on_progress(&mut self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+/// Ignores all progress.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn on_progress(&mut self, _progress: &Progress) {}
+}
+
+/// Prints a progress line to stderr at most once every `interval`.
+#[derive(Debug, Clone)]
+pub struct StderrProgress {
+    pub interval: Duration,
+    last_report: Option<Instant>,
+}
+
+impl StderrProgress {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_report: None,
+        }
+    }
+}
+
+impl Default for StderrProgress {
+    fn default() -> Self {
+        StderrProgress::new(Duration::from_secs(5))
+    }
+}
+
+impl ProgressObserver for StderrProgress {
+    fn on_progress(&mut self, progress: &Progress) {
+        if self
+            .last_report
+            .is_some_and(|last| last.elapsed() < self.interval)
+        {
+            return;
+        }
+        self.last_report = Some(Instant::now());
+
+        let eta = progress
+            .eta
+            .map_or_else(|| "unknown".to_string(), format_duration);
+        eprintln!(
+            "Pass {}: {:.1}%, {} left, {:.2}M rays/s",
+            progress.pass,
+            100. * progress.fraction,
+            eta,
+            progress.rays_per_second / 1e6,
+        );
+    }
+
+    fn on_finished(&mut self, progress: &Progress, cancelled: bool) {
+        let status = if cancelled { "Cancelled" } else { "Done" };
+        eprintln!(
+            "{status} after {}, {:.2}M rays/s",
+            format_duration(progress.elapsed),
+            progress.rays_per_second / 1e6,
+        );
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Lets the caller stop a render early. Clones share the same flag, so a clone can be
+/// handed to another thread or a signal handler.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts the work done by a render and turns it into `Progress` reports.
+#[derive(Debug, Clone)]
+pub struct ProgressTracker {
+    start: Instant,
+    total_samples: u64,
+    initial_samples: u64,
+    samples: u64,
+    rays: u64,
+    pass: u32,
+}
+
+impl ProgressTracker {
+    /// Tracker for a render with a budget of `total_samples`, `initial_samples` of which
+    /// were taken before, by the render being resumed.
+    pub fn new(total_samples: u64, initial_samples: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            total_samples,
+            initial_samples,
+            samples: initial_samples,
+            rays: 0,
+            pass: 0,
+        }
+    }
+
+    pub fn start_pass(&mut self) {
+        self.pass += 1;
+    }
+
+    pub fn add(&mut self, samples: u64, rays: u64) {
+        self.samples += samples;
+        self.rays += rays;
+    }
+
+    pub fn progress(&self) -> Progress {
+        let elapsed = self.start.elapsed();
+        let seconds = elapsed.as_secs_f64();
+        let fraction = if self.total_samples == 0 {
+            1.
+        } else {
+            (self.samples as f64 / self.total_samples as f64).min(1.)
+        };
+
+        // Only samples taken in this run say anything about the speed.
+        let done = (self.samples - self.initial_samples) as f64;
+        let left = self.total_samples.saturating_sub(self.samples) as f64;
+        let eta = (done > 0.).then(|| Duration::from_secs_f64(seconds * left / done));
+
+        Progress {
+            fraction: fraction as Precision,
+            elapsed,
+            eta,
+            rays_per_second: if seconds > 0. {
+                (self.rays as f64 / seconds) as Precision
+            } else {
+                0.
+            },
+            pass: self.pass,
+        }
+    }
+}
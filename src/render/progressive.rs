@@ -0,0 +1,325 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::utility::{color::Color, vec3::Precision};
+
+use super::{adaptive::PixelEstimate, aov::AovBuffer, film::Film, filter::Filter};
+
+/// Settings for rendering in passes, each adding a few samples to every pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressiveSettings {
+    pub samples_per_pass: i32,
+    /// File the render state is saved to, to resume the render later.
+    pub checkpoint: Option<PathBuf>,
+    /// Minimum time between checkpoints. A checkpoint is always written at the end.
+    pub checkpoint_interval: Duration,
+}
+
+impl Default for ProgressiveSettings {
+    fn default() -> Self {
+        let samples_per_pass = 8;
+        let checkpoint = None;
+        let checkpoint_interval = Duration::from_secs(60);
+
+        Self {
+            samples_per_pass,
+            checkpoint,
+            checkpoint_interval,
+        }
+    }
+}
+
+/// Accumulated samples of one view of a render.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewState {
+    pub film: Film,
+    /// Samples taken for every pixel, in row major order.
+    pub estimates: Vec<PixelEstimate>,
+    /// Render passes, if any are asked for.
+    pub aovs: Option<AovBuffer>,
+}
+
+impl ViewState {
+    pub fn new(cols: usize, rows: usize, filter: Filter) -> Self {
+        Self {
+            film: Film::new(cols, rows, filter),
+            estimates: vec![PixelEstimate::default(); cols * rows],
+            aovs: None,
+        }
+    }
+}
+
+/// Camera settings a render state was rendered with. Resuming with other settings would
+/// mix samples that do not belong together.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateSettings {
+    /// Samples per pixel the render is going for. Resumed renders may raise it.
+    pub samples_per_pixel: i32,
+    /// Descriptions of the reconstruction filter, the render region, the integrator, the
+    /// view, the lens and adaptive sampling.
+    pub filter: String,
+    pub region: String,
+    pub integrator: String,
+    pub view: String,
+    pub lens: String,
+    pub adaptive: String,
+}
+
+/// Everything needed to pick a render up where it was left. There is one view per eye
+/// for stereo renders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderState {
+    pub seed: u64,
+    pub settings: StateSettings,
+    pub views: Vec<ViewState>,
+}
+
+const MAGIC: &[u8; 8] = b"RTCKPT6\n";
+
+/// Longest description read back from a checkpoint.
+const MAX_DESCRIPTION: u32 = 1 << 16;
+
+impl RenderState {
+    /// Writes the state to `path`, through a temporary file so that an interrupted write
+    /// never destroys the previous checkpoint.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let mut out = BufWriter::new(File::create(&temporary)?);
+        self.write_to(&mut out)?;
+        out.into_inner()?.sync_all()?;
+
+        std::fs::rename(&temporary, path)
+    }
+
+    /// Reads a state saved with `RenderState::save`. Filtering resumes with `filter`.
+    pub fn load(path: impl AsRef<Path>, filter: Filter) -> io::Result<Self> {
+        RenderState::read_from(&mut BufReader::new(File::open(path)?), filter)
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        out.write_all(&self.settings.samples_per_pixel.to_le_bytes())?;
+        write_string(out, &self.settings.filter)?;
+        write_string(out, &self.settings.region)?;
+        write_string(out, &self.settings.integrator)?;
+        write_string(out, &self.settings.view)?;
+        write_string(out, &self.settings.lens)?;
+        write_string(out, &self.settings.adaptive)?;
+        out.write_all(&(self.views.len() as u32).to_le_bytes())?;
+
+        for view in &self.views {
+            let film = &view.film;
+            out.write_all(&(film.cols() as u32).to_le_bytes())?;
+            out.write_all(&(film.rows() as u32).to_le_bytes())?;
+
+            let (sums, weights) = film.accumulated();
+            for ((sum, weight), estimate) in sums.iter().zip(weights).zip(&view.estimates) {
+                write_color(out, *sum)?;
+                write_precision(out, *weight)?;
+
+                let (estimate_sum, count, skipped, mean, m2) = estimate.raw_parts();
+                write_color(out, estimate_sum)?;
+                out.write_all(&count.to_le_bytes())?;
+                out.write_all(&skipped.to_le_bytes())?;
+                write_precision(out, mean)?;
+                write_precision(out, m2)?;
+            }
+
+            let (light, light_paths) = film.light();
+            out.write_all(&light_paths.to_le_bytes())?;
+            for c in light {
+                write_color(out, *c)?;
+            }
+
+            out.write_all(&[view.aovs.is_some() as u8])?;
+            if let Some(aovs) = &view.aovs {
+                aovs.write_to(out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from(input: &mut impl Read, filter: Filter) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a render checkpoint"));
+        }
+
+        let seed = u64::from_le_bytes(read_bytes(input)?);
+        let settings = StateSettings {
+            samples_per_pixel: i32::from_le_bytes(read_bytes(input)?),
+            filter: read_string(input)?,
+            region: read_string(input)?,
+            integrator: read_string(input)?,
+            view: read_string(input)?,
+            lens: read_string(input)?,
+            adaptive: read_string(input)?,
+        };
+        let view_count = u32::from_le_bytes(read_bytes(input)?);
+
+        let views = (0..view_count)
+            .map(|_| {
+                let cols = u32::from_le_bytes(read_bytes(input)?) as usize;
+                let rows = u32::from_le_bytes(read_bytes(input)?) as usize;
+                let pixels = cols.checked_mul(rows).ok_or_else(|| invalid_data("checkpoint image is too large"))?;
+
+                // The vectors grow as pixels are read, so that a damaged size cannot ask
+                // for more memory than the file holds.
+                let mut sums = Vec::new();
+                let mut weights = Vec::new();
+                let mut estimates = Vec::new();
+                for _ in 0..pixels {
+                    sums.push(read_color(input)?);
+                    weights.push(read_precision(input)?);
+
+                    let estimate_sum = read_color(input)?;
+                    let count = i32::from_le_bytes(read_bytes(input)?);
+                    let skipped = i32::from_le_bytes(read_bytes(input)?);
+                    let mean = read_precision(input)?;
+                    let m2 = read_precision(input)?;
+                    estimates.push(PixelEstimate::from_raw_parts(estimate_sum, count, skipped, mean, m2));
+                }
+
+                let mut film = Film::from_accumulated(cols, rows, filter, sums, weights);
+                let light_paths = u64::from_le_bytes(read_bytes(input)?);
+                let light = (0..pixels).map(|_| read_color(input)).collect::<io::Result<_>>()?;
+                film.set_light(light, light_paths);
+
+                let aovs = match read_bytes(input)? {
+                    [0] => None,
+                    [1] => Some(AovBuffer::read_from(input, cols, rows)?),
+                    _ => return Err(invalid_data("invalid render passes in checkpoint")),
+                };
+
+                Ok(ViewState { film, estimates, aovs })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { seed, settings, views })
+    }
+}
+
+pub(super) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(super) fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn write_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    out.write_all(&(s.len() as u32).to_le_bytes())?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_string(input: &mut impl Read) -> io::Result<String> {
+    let len = u32::from_le_bytes(read_bytes(input)?);
+    if len > MAX_DESCRIPTION {
+        return Err(invalid_data("checkpoint description is too long"));
+    }
+
+    let mut s = String::new();
+    input.take(len as u64).read_to_string(&mut s)?;
+    if s.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(s)
+}
+
+pub(super) fn write_precision(out: &mut impl Write, v: Precision) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+pub(super) fn read_precision(input: &mut impl Read) -> io::Result<Precision> {
+    Ok(Precision::from_le_bytes(read_bytes::<{ size_of::<Precision>() }>(input)?))
+}
+
+pub(super) fn write_color(out: &mut impl Write, c: Color) -> io::Result<()> {
+    write_precision(out, c.x())?;
+    write_precision(out, c.y())?;
+    write_precision(out, c.z())
+}
+
+pub(super) fn read_color(input: &mut impl Read) -> io::Result<Color> {
+    Ok(Color::new(read_precision(input)?, read_precision(input)?, read_precision(input)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        render::aov::{AovSample, SurfaceSample},
+        utility::vec3::Vec3,
+    };
+
+    use super::*;
+
+    #[test]
+    fn state_round_trip() {
+        let mut view = ViewState::new(3, 2, Filter::default());
+        view.film.add_sample(1.2, 0.7, Color::new(0.25, 0.5, 4.));
+        view.estimates[1].add(Color::new(0.25, 0.5, 4.));
+        view.estimates[1].skip();
+        view.film.add_light(2.5, 1.5, Color::new(1., 2., 3.));
+        view.film.add_light_paths(4);
+        let mut passes = view.clone();
+        let mut aovs = AovBuffer::new(3, 2);
+        let mut sample = AovSample {
+            emission: Color::new(0.5, 0.25, 1.),
+            ..Default::default()
+        };
+        aovs.add_sample(1, 2, &sample);
+        sample.surface = Some(SurfaceSample {
+            depth: 2.,
+            normal: Vec3::new(0., 1., 0.),
+            position: Vec3::new(1., 2., 3.),
+            albedo: Color::new(0.5, 0.5, 0.5),
+            material: 42,
+            object_id: 3,
+        });
+        aovs.add_sample(0, 1, &sample);
+        passes.aovs = Some(aovs);
+
+        let settings = StateSettings {
+            samples_per_pixel: 16,
+            filter: format!("{:?}", Filter::default()),
+            region: "None".to_string(),
+            integrator: "PathTracer".to_string(),
+            view: "Perspective".to_string(),
+            lens: "None".to_string(),
+            adaptive: "None".to_string(),
+        };
+        let state = RenderState { seed: 9, settings, views: vec![view, passes] };
+        let mut bytes = Vec::new();
+        state.write_to(&mut bytes).unwrap();
+
+        assert_eq!(RenderState::read_from(&mut &bytes[..], Filter::default()).unwrap(), state);
+        assert!(RenderState::read_from(&mut &bytes[1..], Filter::default()).is_err());
+
+        // A damaged image size is an error, not an allocation failure.
+        let settings = &state.settings;
+        let descriptions = [
+            &settings.filter,
+            &settings.region,
+            &settings.integrator,
+            &settings.view,
+            &settings.lens,
+            &settings.adaptive,
+        ];
+        let size = 24 + 4 * descriptions.len() + descriptions.iter().map(|s| s.len()).sum::<usize>();
+        bytes[size..size + 8].fill(0xff);
+        assert!(RenderState::read_from(&mut &bytes[..], Filter::default()).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use std::ops::Range;
+
+use crate::utility::vec3::Precision;
+
+/// Rectangle of the image, either in pixels or as a fraction of the image size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    /// Pixels from `col_min`, `row_min` up to, but not including, `col_max`, `row_max`.
+    Pixels {
+        col_min: usize,
+        row_min: usize,
+        col_max: usize,
+        row_max: usize,
+    },
+    /// Crop window in `[0, 1]` image coordinates from the top left corner.
+    Crop {
+        x_min: Precision,
+        y_min: Precision,
+        x_max: Precision,
+        y_max: Precision,
+    },
+}
+
+impl Region {
+    /// Rows and columns of a `cols` x `rows` image covered by the region.
+    pub fn pixel_ranges(&self, cols: usize, rows: usize) -> (Range<usize>, Range<usize>) {
+        let (col_min, row_min, col_max, row_max) = match *self {
+            Region::Pixels {
+                col_min,
+                row_min,
+                col_max,
+                row_max,
+            } => (col_min, row_min, col_max, row_max),
+            Region::Crop {
+                x_min,
+                y_min,
+                x_max,
+                y_max,
+            } => {
+                let to_pixel = |v: Precision, size: usize| (v.clamp(0., 1.) * size as Precision).round() as usize;
+                (to_pixel(x_min, cols), to_pixel(y_min, rows), to_pixel(x_max, cols), to_pixel(y_max, rows))
+            }
+        };
+
+        let col_max = col_max.min(cols);
+        let row_max = row_max.min(rows);
+        (row_min.min(row_max)..row_max, col_min.min(col_max)..col_max)
+    }
+}
+
+/// What the image produced by a region render looks like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RegionOutput {
+    /// Only the region.
+    #[default]
+    Cropped,
+    /// The full frame, black outside the region.
+    Composited,
+}
+
+/// Renders only part of the image. Pixels in the region come out exactly as they would
+/// in a render of the full frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderRegion {
+    pub region: Region,
+    pub output: RegionOutput,
+}
@@ -0,0 +1,73 @@
+use fastrand::Rng;
+
+use crate::utility::{color::Color, vec3::Precision};
+
+/// Randomly ends paths carrying little light, boosting the survivors to make up for it,
+/// so the image stays unbiased while less time goes into dim paths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RussianRoulette {
+    /// Bounces every path makes before it can be terminated.
+    pub min_bounces: i32,
+    /// Lower bound of the survival probability, to keep survivors from being boosted
+    /// into fireflies.
+    pub min_survival: Precision,
+}
+
+impl Default for RussianRoulette {
+    fn default() -> Self {
+        let min_bounces = 3;
+        let min_survival = 0.05;
+
+        Self {
+            min_bounces,
+            min_survival,
+        }
+    }
+}
+
+impl RussianRoulette {
+    /// Probability that a path with `throughput` keeps going.
+    pub fn survival_probability(&self, throughput: Color) -> Precision {
+        let brightest = throughput.x().max(throughput.y()).max(throughput.z());
+        brightest.clamp(self.min_survival.clamp(0., 1.), 1.)
+    }
+
+    /// Plays a round for a path that made `bounces` bounces. Returns the throughput the
+    /// path carries on with, or `None` if it is terminated.
+    pub fn play(&self, bounces: i32, throughput: Color, rng: &mut Rng) -> Option<Color> {
+        if bounces < self.min_bounces {
+            return Some(throughput);
+        }
+
+        let survival = self.survival_probability(throughput);
+        if survival >= 1. {
+            return Some(throughput);
+        }
+
+        (rng.f32() < survival).then(|| throughput / survival)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survivors_make_up_for_the_terminated() {
+        let roulette = RussianRoulette::default();
+        let throughput = Color::new(0.3, 0.1, 0.2);
+        let mut rng = Rng::with_seed(1);
+
+        let trials = 100_000;
+        let mut sum = Color::default();
+        for _ in 0..trials {
+            if let Some(t) = roulette.play(5, throughput, &mut rng) {
+                sum += t;
+            }
+        }
+        let mean = sum / trials as Precision;
+
+        assert!((mean - throughput).len() < 0.01, "{mean}");
+        assert_eq!(roulette.play(2, throughput, &mut rng), Some(throughput));
+    }
+}
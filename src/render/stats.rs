@@ -0,0 +1,204 @@
+use std::{
+    cell::Cell,
+    fmt, fs,
+    io::{self, Write},
+    ops::Sub,
+    path::PathBuf,
+    time::Duration,
+};
+
+/// How `Camera::render` reports the statistics of a render.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatsOutput {
+    /// Prints a human readable summary to stderr.
+    Summary,
+    /// Writes the statistics as JSON to the given file.
+    Json(PathBuf),
+}
+
+/// Work counters, kept per thread so that hot code can bump them without threading
+/// a collector through every call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Counters {
+    pub camera_rays: u64,
+    pub secondary_rays: u64,
+    /// Ray-primitive intersection tests.
+    pub primitive_tests: u64,
+    /// Paths that hit the bounce limit.
+    pub max_depth_terminations: u64,
+    pub russian_roulette_terminations: u64,
+    /// Samples and light splats that came out NaN or infinite, and were dropped.
+    pub invalid_samples: u64,
+}
+
+impl Counters {
+    pub fn rays(&self) -> u64 {
+        self.camera_rays + self.secondary_rays
+    }
+
+    /// Average number of segments in a path, counting the camera ray.
+    pub fn average_path_length(&self) -> f64 {
+        if self.camera_rays == 0 {
+            0.
+        } else {
+            self.rays() as f64 / self.camera_rays as f64
+        }
+    }
+}
+
+impl Sub for Counters {
+    type Output = Counters;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Counters {
+            camera_rays: self.camera_rays - rhs.camera_rays,
+            secondary_rays: self.secondary_rays - rhs.secondary_rays,
+            primitive_tests: self.primitive_tests - rhs.primitive_tests,
+            max_depth_terminations: self.max_depth_terminations - rhs.max_depth_terminations,
+            russian_roulette_terminations: self.russian_roulette_terminations
+                - rhs.russian_roulette_terminations,
+            invalid_samples: self.invalid_samples - rhs.invalid_samples,
+        }
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = Cell::new(Counters::default());
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Turns counting on or off for the current thread, returning whether it was on. It is
+/// off by default, so that hot code pays for the counters only when they are reported.
+pub fn set_enabled(enabled: bool) -> bool {
+    ENABLED.with(|cell| cell.replace(enabled))
+}
+
+/// Updates the counters of the current thread, if counting is on.
+pub fn record(f: impl FnOnce(&mut Counters)) {
+    if !ENABLED.with(Cell::get) {
+        return;
+    }
+
+    COUNTERS.with(|counters| {
+        let mut value = counters.get();
+        f(&mut value);
+        counters.set(value);
+    });
+}
+
+/// The counters of the current thread. They only ever grow, subtract two snapshots to
+/// get the work done in between.
+pub fn snapshot() -> Counters {
+    COUNTERS.with(Cell::get)
+}
+
+/// Time spent in each phase of a render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timings {
+    /// Setting up the views and the sample budget.
+    pub setup: Duration,
+    /// Tracing samples.
+    pub sampling: Duration,
+    /// Writing checkpoints.
+    pub checkpointing: Duration,
+    /// Turning the film into the final image.
+    pub resolve: Duration,
+    /// Writing the image out, only done by `Camera::render`.
+    pub output: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.setup + self.sampling + self.checkpointing + self.resolve + self.output
+    }
+}
+
+/// Statistics gathered over a render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RenderStats {
+    pub counters: Counters,
+    pub timings: Timings,
+}
+
+impl RenderStats {
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.timings.sampling.as_secs_f64();
+        if seconds > 0. {
+            self.counters.rays() as f64 / seconds
+        } else {
+            0.
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let c = &self.counters;
+        let t = &self.timings;
+        format!(
+            concat!(
+                "{{\n",
+                "  \"camera_rays\": {},\n",
+                "  \"secondary_rays\": {},\n",
+                "  \"primitive_tests\": {},\n",
+                "  \"average_path_length\": {},\n",
+                "  \"max_depth_terminations\": {},\n",
+                "  \"russian_roulette_terminations\": {},\n",
+                "  \"invalid_samples\": {},\n",
+                "  \"rays_per_second\": {},\n",
+                "  \"timings\": {{\n",
+                "    \"setup\": {},\n",
+                "    \"sampling\": {},\n",
+                "    \"checkpointing\": {},\n",
+                "    \"resolve\": {},\n",
+                "    \"output\": {},\n",
+                "    \"total\": {}\n",
+                "  }}\n",
+                "}}\n",
+            ),
+            c.camera_rays,
+            c.secondary_rays,
+            c.primitive_tests,
+            c.average_path_length(),
+            c.max_depth_terminations,
+            c.russian_roulette_terminations,
+            c.invalid_samples,
+            self.rays_per_second(),
+            t.setup.as_secs_f64(),
+            t.sampling.as_secs_f64(),
+            t.checkpointing.as_secs_f64(),
+            t.resolve.as_secs_f64(),
+            t.output.as_secs_f64(),
+            t.total().as_secs_f64(),
+        )
+    }
+
+    /// Reports the statistics the way `output` asks for.
+    pub fn report(&self, output: &StatsOutput) -> io::Result<()> {
+        match output {
+            StatsOutput::Summary => write!(io::stderr(), "{self}"),
+            StatsOutput::Json(path) => fs::write(path, self.to_json()),
+        }
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = &self.counters;
+        let t = &self.timings;
+        writeln!(f, "Render statistics")?;
+        writeln!(f, "  Camera rays:                   {}", c.camera_rays)?;
+        writeln!(f, "  Secondary rays:                {}", c.secondary_rays)?;
+        writeln!(f, "  Primitive tests:               {}", c.primitive_tests)?;
+        writeln!(f, "  Average path length:           {:.2}", c.average_path_length())?;
+        writeln!(f, "  Max depth terminations:        {}", c.max_depth_terminations)?;
+        writeln!(f, "  Russian roulette terminations: {}", c.russian_roulette_terminations)?;
+        writeln!(f, "  Invalid samples:               {}", c.invalid_samples)?;
+        writeln!(f, "  Rays per second:               {:.0}", self.rays_per_second())?;
+        writeln!(f, "Timings")?;
+        writeln!(f, "  Setup:         {:.3}s", t.setup.as_secs_f64())?;
+        writeln!(f, "  Sampling:      {:.3}s", t.sampling.as_secs_f64())?;
+        writeln!(f, "  Checkpointing: {:.3}s", t.checkpointing.as_secs_f64())?;
+        writeln!(f, "  Resolve:       {:.3}s", t.resolve.as_secs_f64())?;
+        writeln!(f, "  Output:        {:.3}s", t.output.as_secs_f64())?;
+        writeln!(f, "  Total:         {:.3}s", t.total().as_secs_f64())
+    }
+}
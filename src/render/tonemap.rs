@@ -0,0 +1,204 @@
+use crate::utility::{color::Color, vec3::Precision};
+
+/// Scales scene radiance before it is tone mapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exposure {
+    /// Exposure compensation in stops, every stop doubles the brightness.
+    Ev(Precision),
+    /// Exposure of a physical camera, taking scene values as luminance in cd/m².
+    /// `shutter` is in seconds. See `Camera::with_physical_exposure` to take `f_number`
+    /// from the camera's lens.
+    Physical {
+        iso: Precision,
+        shutter: Precision,
+        f_number: Precision,
+    },
+}
+
+impl Exposure {
+    /// Factor scene radiance is multiplied by.
+    pub fn scale(&self) -> Precision {
+        match *self {
+            Exposure::Ev(ev) => ev.exp2(),
+            Exposure::Physical {
+                iso,
+                shutter,
+                f_number,
+            } => {
+                // Saturation based sensitivity, with the 78% reflectance headroom
+                // of ISO 12232 folded into the 1.2 factor.
+                let ev100 = (f_number * f_number / shutter * 100. / iso).log2();
+                1. / (1.2 * ev100.exp2())
+            }
+        }
+    }
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure::Ev(0.)
+    }
+}
+
+/// Curve mapping exposed scene radiance to display values in `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ToneMap {
+    /// Clip everything above 1.
+    #[default]
+    Clamp,
+    /// Extended Reinhard, `white` is the smallest value mapped to 1.
+    Reinhard { white: Precision },
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+    /// Troy Sobotka's AgX, using Benjamin Wrensch's polynomial fit of the base contrast.
+    Agx,
+}
+
+impl ToneMap {
+    /// Maps a linear scene color to a linear display color in `[0, 1]`.
+    pub fn apply(&self, c: Color) -> Color {
+        let c = map_channels(c, |x| x.max(0.));
+
+        let mapped = match *self {
+            ToneMap::Clamp => c,
+            ToneMap::Reinhard { white } => {
+                map_channels(c, |x| x * (1. + x / (white * white)) / (1. + x))
+            }
+            ToneMap::Hable => {
+                const EXPOSURE_BIAS: Precision = 2.;
+                const WHITE: Precision = 11.2;
+                let white_scale = 1. / hable_partial(WHITE);
+                map_channels(c, |x| hable_partial(EXPOSURE_BIAS * x) * white_scale)
+            }
+            ToneMap::Aces => aces_fitted(c),
+            ToneMap::Agx => agx(c),
+        };
+
+        map_channels(mapped, |x| x.clamp(0., 1.))
+    }
+}
+
+/// Scene to display transform applied to the rendered image.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DisplayTransform {
+    pub exposure: Exposure,
+    pub tone_map: ToneMap,
+}
+
+impl DisplayTransform {
+    pub fn apply(&self, c: Color) -> Color {
+        self.tone_map.apply(self.exposure.scale() * c)
+    }
+}
+
+fn map_channels(c: Color, f: impl Fn(Precision) -> Precision) -> Color {
+    Color::new(f(c.x()), f(c.y()), f(c.z()))
+}
+
+/// Row major 3x3 matrix times a color.
+fn mul_matrix(m: &[[Precision; 3]; 3], c: Color) -> Color {
+    let row = |r: &[Precision; 3]| r[0] * c.x() + r[1] * c.y() + r[2] * c.z();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn hable_partial(x: Precision) -> Precision {
+    const A: Precision = 0.15;
+    const B: Precision = 0.50;
+    const C: Precision = 0.10;
+    const D: Precision = 0.20;
+    const E: Precision = 0.02;
+    const F: Precision = 0.30;
+
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+fn aces_fitted(c: Color) -> Color {
+    // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+    const INPUT: [[Precision; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.076, 0.90834, 0.01566],
+        [0.0284, 0.13383, 0.83777],
+    ];
+    // ODT_SAT => XYZ => D60_2_D65 => sRGB
+    const OUTPUT: [[Precision; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+
+    let c = mul_matrix(&INPUT, c);
+    let c = map_channels(c, |v| {
+        (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081)
+    });
+    mul_matrix(&OUTPUT, c)
+}
+
+fn agx(c: Color) -> Color {
+    const INSET: [[Precision; 3]; 3] = [
+        [0.84247906, 0.0784336, 0.079223745],
+        [0.042328242, 0.87846864, 0.07916613],
+        [0.042375655, 0.0784336, 0.879143],
+    ];
+    const OUTSET: [[Precision; 3]; 3] = [
+        [1.196879, -0.09802088, -0.09902974],
+        [-0.052896852, 1.1519031, -0.098961177],
+        [-0.052971636, -0.09804345, 1.1510737],
+    ];
+    const MIN_EV: Precision = -12.47393;
+    const MAX_EV: Precision = 4.026069;
+
+    let c = mul_matrix(&INSET, c);
+    let c = map_channels(c, |v| {
+        let v = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let v2 = v * v;
+        let v4 = v2 * v2;
+        15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v
+            - 0.00232
+    });
+    let c = mul_matrix(&OUTSET, c);
+
+    // The curve produces display encoded values, take them back to linear.
+    map_channels(c, |v| v.max(0.).powf(2.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_are_monotonic_and_bounded() {
+        let curves = [
+            ToneMap::Clamp,
+            ToneMap::Reinhard { white: 4. },
+            ToneMap::Hable,
+            ToneMap::Aces,
+            ToneMap::Agx,
+        ];
+
+        for curve in curves {
+            let mut previous = 0.;
+            for i in 0..=64 {
+                let x = (i as Precision / 4. - 8.).exp2();
+                let y = curve.apply(Color::new(x, x, x)).y();
+                assert!((0. ..=1.).contains(&y), "{curve:?} {x} {y}");
+                assert!(y >= previous, "{curve:?} {x} {y}");
+                previous = y;
+            }
+        }
+    }
+
+    #[test]
+    fn physical_exposure_matches_ev() {
+        // f/1, one second at ISO 100 is EV 0.
+        let physical = Exposure::Physical {
+            iso: 100.,
+            shutter: 1.,
+            f_number: 1.,
+        };
+
+        assert!((physical.scale() - 1. / 1.2).abs() < 1e-6);
+        assert_eq!(Exposure::Ev(2.).scale(), 4.);
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt::{self, Write};
+
+use crate::{
+    figures::hittable::HitRecord,
+    integrators::integrator::Termination,
+    materials::material::{BsdfSample, Lobe},
+    utility::{
+        color::Color,
+        ray::Ray,
+        vec3::{Point3, Precision, Vec3},
+    },
+};
+
+/// Everything one sample of one pixel went through, see `Camera::trace_pixel`. Printed
+/// with `Display` as text, or with `PixelTrace::to_json`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelTrace {
+    pub row: usize,
+    pub col: usize,
+    pub sample: u64,
+    /// The camera ray, `None` if the projection sees nothing there.
+    pub ray: Option<Ray>,
+    /// Weight of the light the camera ray brings back to the film.
+    pub weight: Color,
+    /// Light the sample adds to the film.
+    pub color: Color,
+    pub rays: u64,
+    pub termination: Termination,
+    /// Surfaces the camera path hit, in order.
+    pub bounces: Vec<Bounce>,
+}
+
+/// A surface hit along a traced path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bounce {
+    pub object_id: u32,
+    pub point: Point3,
+    pub normal: Vec3,
+    pub front_face: bool,
+    /// The material, as printed with `Debug`.
+    pub material: String,
+    /// Light given off by the surface, before weighting by the throughput.
+    pub emitted: Color,
+    /// The ray the material scattered, `None` if it absorbed the path.
+    pub scattered: Option<Scattering>,
+    /// Weight of the light found further down the path, after this bounce and any
+    /// Russian roulette boost. Black if the path ends here.
+    pub throughput: Color,
+}
+
+impl Bounce {
+    /// A bounce off the surface `rec`, not scattering yet.
+    pub fn new(rec: &HitRecord) -> Self {
+        Self {
+            object_id: rec.object_id,
+            point: rec.p,
+            normal: rec.normal,
+            front_face: rec.front_face,
+            material: format!("{:?}", rec.material),
+            emitted: rec.material.emitted(rec),
+            scattered: None,
+            throughput: Color::new(0., 0., 0.),
+        }
+    }
+}
+
+/// The ray scattered at a bounce.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scattering {
+    pub direction: Vec3,
+    pub attenuation: Color,
+    pub lobe: Lobe,
+    /// Density per solid angle of the direction, 0 for discrete directions.
+    pub pdf: Precision,
+}
+
+impl From<&BsdfSample> for Scattering {
+    fn from(sample: &BsdfSample) -> Self {
+        Self {
+            direction: *sample.ray.direction(),
+            attenuation: sample.weight,
+            lobe: sample.lobe,
+            pdf: sample.pdf,
+        }
+    }
+}
+
+impl PixelTrace {
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = self.write_json(&mut json);
+        json
+    }
+
+    fn write_json(&self, out: &mut String) -> fmt::Result {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"row\": {},", self.row)?;
+        writeln!(out, "  \"col\": {},", self.col)?;
+        writeln!(out, "  \"sample\": {},", self.sample)?;
+        match &self.ray {
+            Some(ray) => writeln!(
+                out,
+                "  \"ray\": {{ \"origin\": {}, \"direction\": {} }},",
+                json_vector(*ray.origin()),
+                json_vector(*ray.direction())
+            )?,
+            None => writeln!(out, "  \"ray\": null,")?,
+        }
+        writeln!(out, "  \"weight\": {},", json_vector(self.weight))?;
+        writeln!(out, "  \"color\": {},", json_vector(self.color))?;
+        writeln!(out, "  \"rays\": {},", self.rays)?;
+        writeln!(out, "  \"termination\": \"{:?}\",", self.termination)?;
+        writeln!(out, "  \"bounces\": [")?;
+
+        for (i, bounce) in self.bounces.iter().enumerate() {
+            writeln!(out, "    {{")?;
+            writeln!(out, "      \"object_id\": {},", bounce.object_id)?;
+            writeln!(out, "      \"point\": {},", json_vector(bounce.point))?;
+            writeln!(out, "      \"normal\": {},", json_vector(bounce.normal))?;
+            writeln!(out, "      \"front_face\": {},", bounce.front_face)?;
+            writeln!(out, "      \"material\": \"{}\",", json_escape(&bounce.material))?;
+            writeln!(out, "      \"emitted\": {},", json_vector(bounce.emitted))?;
+            match &bounce.scattered {
+                Some(s) => writeln!(
+                    out,
+                    "      \"scattered\": {{ \"direction\": {}, \"attenuation\": {}, \"lobe\": \"{:?}\", \"pdf\": {} }},",
+                    json_vector(s.direction),
+                    json_vector(s.attenuation),
+                    s.lobe,
+                    json_number(s.pdf)
+                )?,
+                None => writeln!(out, "      \"scattered\": null,")?,
+            }
+            writeln!(out, "      \"throughput\": {}", json_vector(bounce.throughput))?;
+            let separator = if i + 1 < self.bounces.len() { "," } else { "" };
+            writeln!(out, "    }}{separator}")?;
+        }
+
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+}
+
+impl fmt::Display for PixelTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pixel row {}, col {}, sample {}", self.row, self.col, self.sample)?;
+        match &self.ray {
+            Some(ray) => writeln!(f, "  Camera ray:  {} -> {}", vector(*ray.origin()), vector(*ray.direction()))?,
+            None => writeln!(f, "  Camera ray:  none")?,
+        }
+        writeln!(f, "  Weight:      {}", vector(self.weight))?;
+        writeln!(f, "  Color:       {}", vector(self.color))?;
+        writeln!(f, "  Rays:        {}", self.rays)?;
+        writeln!(f, "  Termination: {:?}", self.termination)?;
+
+        for (i, bounce) in self.bounces.iter().enumerate() {
+            writeln!(f, "Bounce {i}")?;
+            writeln!(f, "  Object:      {}", bounce.object_id)?;
+            writeln!(f, "  Point:       {}", vector(bounce.point))?;
+            let face = if bounce.front_face { "front" } else { "back" };
+            writeln!(f, "  Normal:      {} ({face} face)", vector(bounce.normal))?;
+            writeln!(f, "  Material:    {}", bounce.material)?;
+            writeln!(f, "  Emitted:     {}", vector(bounce.emitted))?;
+            match &bounce.scattered {
+                Some(s) => {
+                    writeln!(f, "  Scattered:   {} ({:?})", vector(s.direction), s.lobe)?;
+                    writeln!(f, "  Attenuation: {}", vector(s.attenuation))?;
+                    writeln!(f, "  PDF:         {}", s.pdf)?;
+                }
+                None => writeln!(f, "  Scattered:   none")?,
+            }
+            writeln!(f, "  Throughput:  {}", vector(bounce.throughput))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn vector(v: Vec3) -> String {
+    format!("({}, {}, {})", v.x(), v.y(), v.z())
+}
+
+/// JSON has no NaN or infinity, which are what fireflies are often made of, so they are
+/// written as `null`.
+fn json_number(v: Precision) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_vector(v: Vec3) -> String {
+    format!("[{}, {}, {}]", json_number(v.x()), json_number(v.y()), json_number(v.z()))
+}
+
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,39 @@
+use std::rc::Rc;
+
+use crate::utility::{color::Color, vec3::{Point3, Precision}};
+
+use super::texture::{SolidColor, Texture};
+
+/// Alternates between two textures in cubes of side `scale` filling space.
+#[derive(Debug, Clone)]
+pub struct CheckerTexture {
+    inv_scale: Precision,
+    even: Rc<dyn Texture>,
+    odd: Rc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: Precision, even: Rc<dyn Texture>, odd: Rc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1. / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: Precision, even: Color, odd: Color) -> Self {
+        Self::new(scale, Rc::new(SolidColor::new(even)), Rc::new(SolidColor::new(odd)))
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: Precision, v: Precision, p: &Point3) -> Color {
+        let cell = (0..3).map(|axis| (self.inv_scale * p[axis]).floor() as i64).sum::<i64>();
+
+        if cell.rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
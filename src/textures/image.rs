@@ -0,0 +1,115 @@
+use std::fmt;
+
+use crate::{
+    image_formats::ppm::PPM,
+    utility::{
+        color::{linear_to_srgb, Color},
+        utils::fnv1a,
+        vec3::{Point3, Precision},
+    },
+};
+
+use super::texture::Texture;
+
+/// Image wrapped around surfaces by their `u`, `v` coordinates, with `v` going up the
+/// image, bilinearly filtered and repeating outside `[0, 1]`.
+#[derive(Clone, PartialEq)]
+pub struct ImageTexture {
+    image: PPM,
+}
+
+impl ImageTexture {
+    /// Texture of colors, from an image decoded from sRGB like `PPM::load` does.
+    pub fn new(image: PPM) -> Self {
+        Self { image }
+    }
+
+    /// Texture of data stored as is rather than as sRGB colors, such as glTF
+    /// metallic-roughness maps, from an image decoded like `PPM::load` does.
+    pub fn data(image: PPM) -> Self {
+        Self::new(image.map(|c| Color::new(linear_to_srgb(c.x()), linear_to_srgb(c.y()), linear_to_srgb(c.z()))))
+    }
+
+    fn texel(&self, row: i64, col: i64) -> Color {
+        let rows = self.image.rows() as i64;
+        let cols = self.image.cols() as i64;
+        self.image.get(row.rem_euclid(rows) as usize, col.rem_euclid(cols) as usize)
+    }
+}
+
+// The pixels would make descriptions, and the material ids hashed from them, huge.
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<u8> = (self.image.values().iter())
+            .flat_map(|c| [c.x(), c.y(), c.z()])
+            .flat_map(Precision::to_le_bytes)
+            .collect();
+
+        f.debug_struct("ImageTexture")
+            .field("cols", &self.image.cols())
+            .field("rows", &self.image.rows())
+            .field("checksum", &format_args!("{:016x}", fnv1a(&bytes)))
+            .finish()
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Precision, v: Precision, _p: &Point3) -> Color {
+        if self.image.values().is_empty() {
+            return Color::new(1., 0., 1.);
+        }
+
+        let x = u * self.image.cols() as Precision - 0.5;
+        let y = (1. - v) * self.image.rows() as Precision - 0.5;
+        let (col, row) = (x.floor(), y.floor());
+        let (fx, fy) = (x - col, y - row);
+        let (col, row) = (col as i64, row as i64);
+
+        let top = (1. - fx) * self.texel(row, col) + fx * self.texel(row, col + 1);
+        let bottom = (1. - fx) * self.texel(row + 1, col) + fx * self.texel(row + 1, col + 1);
+        (1. - fy) * top + fy * bottom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_texel_centers_and_wraps() {
+        let image = PPM::new(
+            2,
+            1,
+            255,
+            vec![Color::new(1., 0., 0.), Color::new(0., 0., 1.)],
+        );
+        let texture = ImageTexture::new(image);
+        let p = Point3::default();
+
+        assert_eq!(texture.value(0.25, 0.5, &p), Color::new(1., 0., 0.));
+        assert_eq!(texture.value(0.75, 0.5, &p), Color::new(0., 0., 1.));
+        assert_eq!(texture.value(0.5, 0.5, &p), Color::new(0.5, 0., 0.5));
+        assert_eq!(texture.value(1.25, 0.5, &p), Color::new(1., 0., 0.));
+        assert_eq!(texture.value(0., 0.5, &p), Color::new(0.5, 0., 0.5));
+    }
+
+    #[test]
+    fn data_keeps_stored_values() {
+        let image = PPM::read_from(&b"P3\n1 1\n255\n0 51 255\n"[..]).unwrap();
+        let p = Point3::default();
+
+        let value = ImageTexture::data(image.clone()).value(0.5, 0.5, &p);
+        assert!((value - Color::new(0., 0.2, 1.)).len() < 1e-5, "{value}");
+        assert!(ImageTexture::new(image).value(0.5, 0.5, &p).y() < 0.05);
+    }
+
+    #[test]
+    fn describes_pixels_by_checksum() {
+        let red = ImageTexture::new(PPM::new(1, 1, 255, vec![Color::new(1., 0., 0.)]));
+        let blue = ImageTexture::new(PPM::new(1, 1, 255, vec![Color::new(0., 0., 1.)]));
+
+        assert!(format!("{red:?}").len() < 80);
+        assert_ne!(format!("{red:?}"), format!("{blue:?}"));
+        assert_eq!(format!("{red:?}"), format!("{:?}", red.clone()));
+    }
+}
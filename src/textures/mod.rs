@@ -0,0 +1,3 @@
+pub mod texture;
+pub mod checker;
+pub mod image;
@@ -0,0 +1,101 @@
+use std::{fmt::Debug, rc::Rc};
+
+use crate::{
+    figures::hittable::HitRecord,
+    utility::{
+        color::Color,
+        vec3::{Point3, Precision},
+    },
+};
+
+/// Value varying over surfaces, looked up by surface coordinates or position. Scalar
+/// parameters read the first channel, see `Channel` to read another one.
+pub trait Texture: Debug {
+    fn value(&self, u: Precision, v: Precision, p: &Point3) -> Color;
+
+    /// Value at the hit point.
+    fn at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, &rec.p)
+    }
+
+    /// First channel of the value at the hit point.
+    fn scalar_at(&self, rec: &HitRecord) -> Precision {
+        self.at(rec).x()
+    }
+}
+
+/// The same value everywhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+
+    /// A scalar parameter, as a texture.
+    pub fn gray(value: Precision) -> Self {
+        Self::new(Color::new(value, value, value))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: Precision, _v: Precision, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// A channel of a color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChannel {
+    Red,
+    Green,
+    Blue,
+}
+
+/// One channel of a texture, in every channel. glTF packs several scalar maps in one
+/// image, like roughness in green and metallic in blue.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    texture: Rc<dyn Texture>,
+    channel: ColorChannel,
+}
+
+impl Channel {
+    pub fn new(texture: Rc<dyn Texture>, channel: ColorChannel) -> Self {
+        Self { texture, channel }
+    }
+}
+
+impl Texture for Channel {
+    fn value(&self, u: Precision, v: Precision, p: &Point3) -> Color {
+        let color = self.texture.value(u, v, p);
+        let value = match self.channel {
+            ColorChannel::Red => color.x(),
+            ColorChannel::Green => color.y(),
+            ColorChannel::Blue => color.z(),
+        };
+        Color::new(value, value, value)
+    }
+}
+
+/// A texture multiplied by a factor, like the factors glTF applies to its textures.
+#[derive(Debug, Clone)]
+pub struct Scale {
+    texture: Rc<dyn Texture>,
+    factor: Color,
+}
+
+impl Scale {
+    pub fn new(texture: Rc<dyn Texture>, factor: Color) -> Self {
+        Self { texture, factor }
+    }
+}
+
+impl Texture for Scale {
+    fn value(&self, u: Precision, v: Precision, p: &Point3) -> Color {
+        self.factor * self.texture.value(u, v, p)
+    }
+}
@@ -1,26 +1,47 @@
+use std::io::{self, Write};
+
 use crate::utility::vec3::Vec3;
 
 use super::{interval::Interval, vec3::Precision};
 
 pub type Color = Vec3;
 
-fn linear_to_gamma(linear_component: Precision) -> Precision {
-    if linear_component > 0. {
-        return linear_component.sqrt();
+/// sRGB transfer function (IEC 61966-2-1), from linear light to encoded values.
+pub fn linear_to_srgb(linear_component: Precision) -> Precision {
+    if linear_component <= 0. {
+        return 0.;
     }
 
-    0.
+    if linear_component <= 0.0031308 {
+        12.92 * linear_component
+    } else {
+        1.055 * linear_component.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `linear_to_srgb`.
+pub fn srgb_to_linear(encoded_component: Precision) -> Precision {
+    if encoded_component <= 0.04045 {
+        encoded_component / 12.92
+    } else {
+        ((encoded_component + 0.055) / 1.055).powf(2.4)
+    }
 }
 
 impl Color {
-    pub fn write_color(&self) {
+    /// Relative luminance of a linear Rec. 709 color.
+    pub fn luminance(&self) -> Precision {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
+
+    pub fn write_color(&self, out: &mut impl Write) -> io::Result<()> {
         let r = self.x();
         let g = self.y();
         let b = self.z();
 
-        let r = linear_to_gamma(r);
-        let g = linear_to_gamma(g);
-        let b = linear_to_gamma(b);
+        let r = linear_to_srgb(r);
+        let g = linear_to_srgb(g);
+        let b = linear_to_srgb(b);
 
         // Translate from [0,1] to [0,255]
         let intensity = Interval::new(0.0, 0.999);
@@ -28,6 +49,6 @@ impl Color {
         let gbyte = (256. * intensity.clamp(g)) as i32;
         let bbyte = (256. * intensity.clamp(b)) as i32;
 
-        println!("{} {} {}", rbyte, gbyte, bbyte);
+        writeln!(out, "{} {} {}", rbyte, gbyte, bbyte)
     }
 }
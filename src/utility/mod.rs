@@ -8,6 +8,8 @@ pub mod utils {
     // use std::f64::consts::PI as pi64;
     pub use std::f32::consts::PI as pi32;
 
+    use fastrand::Rng;
+
     use super::vec3::Precision;
 
     #[inline(always)]
@@ -16,7 +18,30 @@ pub mod utils {
     }
 
     #[inline(always)]
-    pub fn random_f32(min: f32, max: f32) -> f32 {
-        min + (max - min) * fastrand::f32()
+    pub fn random_f32(rng: &mut Rng, min: f32, max: f32) -> f32 {
+        min + (max - min) * rng.f32()
+    }
+
+    /// Derives an independent stream seed from a base seed and a stream index
+    /// (SplitMix64 finalizer), so neighbouring indices get uncorrelated streams.
+    #[inline(always)]
+    pub fn mix_seed(seed: u64, stream: u64) -> u64 {
+        let mut z = seed ^ stream.wrapping_add(0x9e37_79b9_7f4a_7c15).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// 64 bit FNV-1a hash of `bytes`. Unlike the std hashers, it is fixed, so hashes can be
+    /// saved and compared across builds.
+    pub fn fnv1a(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+    }
+
+    /// Random generator for one sample of a pixel. It only depends on the seed, the pixel
+    /// coordinates and the sample index, so a sample comes out the same no matter the
+    /// order or the passes samples are taken in.
+    pub fn pixel_rng(seed: u64, row: u64, col: u64, sample: u64) -> Rng {
+        Rng::with_seed(mix_seed(mix_seed(mix_seed(seed, row), col), sample))
     }
 }
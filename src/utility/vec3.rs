@@ -1,5 +1,7 @@
 use std::{fmt::Display, ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign}};
 
+use fastrand::Rng;
+
 use super::utils::random_f32;
 
 pub type Precision = f32;
@@ -36,18 +38,23 @@ impl Vec3 {
         self.x.powi(2) + self.y.powi(2) + self.z.powi(2)    
     }
 
+    /// Whether no component is NaN or infinite.
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1e-4;
 
         self.x.abs() < s && self.y.abs() < s && self.z.abs() < s
     }
 
-    pub fn random() -> Self {
-        Self { x: fastrand::f32(), y: fastrand::f32(), z: fastrand::f32() }
+    pub fn random(rng: &mut Rng) -> Self {
+        Self { x: rng.f32(), y: rng.f32(), z: rng.f32() }
     }
 
-    pub fn random_bounded(min: Precision, max: Precision) -> Self {
-        Self { x: random_f32(min, max), y: random_f32(min, max), z: random_f32(min, max) }
+    pub fn random_bounded(rng: &mut Rng, min: Precision, max: Precision) -> Self {
+        Self { x: random_f32(rng, min, max), y: random_f32(rng, min, max), z: random_f32(rng, min, max) }
     }
 
     pub fn dot(&self, rhs: &Vec3) -> Precision {
@@ -67,9 +74,9 @@ impl Vec3 {
         self / len
     }
 
-    pub fn random_unit_vec() -> Self {
+    pub fn random_unit_vec(rng: &mut Rng) -> Self {
         loop {
-            let p = Vec3::random_bounded(-1., 1.);
+            let p = Vec3::random_bounded(rng, -1., 1.);
             let lensq = p.len_square();
             if Precision::MIN < lensq && lensq <= 1. {
                 return p / lensq.sqrt();
@@ -77,8 +84,8 @@ impl Vec3 {
         }
     }
 
-    pub fn random_on_hemisphere(normal: &Vec3) -> Self {
-        let on_unit_sphere = Vec3::random_unit_vec();
+    pub fn random_on_hemisphere(rng: &mut Rng, normal: &Vec3) -> Self {
+        let on_unit_sphere = Vec3::random_unit_vec(rng);
         if on_unit_sphere.dot(normal) > 0. {
             on_unit_sphere
         } else {
@@ -86,9 +93,9 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_disk() -> Self {
+    pub fn random_in_unit_disk(rng: &mut Rng) -> Self {
         loop {
-            let p = Vec3::new(random_f32(-1., 1.), random_f32(-1., 1.), 0.);
+            let p = Vec3::new(random_f32(rng, -1., 1.), random_f32(rng, -1., 1.), 0.);
             if p.len_square() < 1. {
                 return p;
             }